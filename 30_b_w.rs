@@ -1,8 +1,16 @@
+use crate::dist::{self, ExactDist, Prob};
+use crate::deck::{Deck, RANKS};
+use crate::rule::{self, StoppingRule};
+use smallvec::SmallVec;
 use std::collections::{BTreeMap, HashMap};
 
-/// Pack counts (10 ranks) into a compact u64 key.
-/// Each count is 0..=15 (we only need 0..=4 here), we use 4 bits per rank.
-fn pack_counts(counts: &[u8; 10]) -> u64 {
+/// Pack counts (`RANKS` ranks, indexed as in `deck`) into a compact u64 key.
+/// Each count is 0..=15, 4 bits per rank: `distributions` takes only decks
+/// with at most `MAX_PER_VALUE` cards of every rank, such as standard-40,
+/// spanish-40 with its twelve tens, or thirteen-52, and rejects the rest,
+/// standard-52 with its sixteen tens and the multi-deck shoes among them.
+#[inline]
+pub fn pack_counts(counts: &[u8; RANKS]) -> u64 {
     let mut key: u64 = 0;
     for (i, &c) in counts.iter().enumerate() {
         key |= (c as u64) << (4 * i);
@@ -10,125 +18,204 @@ fn pack_counts(counts: &[u8; 10]) -> u64 {
     key
 }
 
-fn unpack_counts(mut key: u64) -> [u8; 10] {
-    let mut counts = [0u8; 10];
-    for i in 0..10 {
-        counts[i] = (key & 0xF) as u8;
+/// The inverse of `pack_counts`.
+#[inline]
+pub fn unpack_counts(mut key: u64) -> [u8; RANKS] {
+    let mut counts = [0u8; RANKS];
+    for count in counts.iter_mut() {
+        *count = (key & 0xF) as u8;
         key >>= 4;
     }
     counts
 }
 
-/// State key for memoization: (packed_counts, current_total, run_length)
+/// The count of `rank` (an index into the counts) in a packed key.
+#[inline]
+pub fn count_of(key: u64, rank: usize) -> u8 {
+    ((key >> (4 * rank)) & 0xF) as u8
+}
+
+/// The packed key with one card of `rank` removed. The count must be
+/// nonzero: the nibble would borrow from the next rank otherwise.
+#[inline]
+pub fn dec_rank(key: u64, rank: usize) -> u64 {
+    debug_assert!(count_of(key, rank) > 0);
+    key - (1 << (4 * rank))
+}
+
+/// Most cards of one value a packed deck can hold, in its 4 bits.
+const MAX_PER_VALUE: u16 = 15;
+
+// Every rank's four bits fit in the u64 key.
+const _: () = assert!(4 * RANKS as u32 <= u64::BITS);
+
+// Run lengths are u8, which holds the longest row: the whole of a full
+// packed deck, 150 cards.
+const _: () = assert!(MAX_PER_VALUE as usize * RANKS <= u8::MAX as usize);
+
+/// State key for memoization: (packed_counts, current_total). The cards
+/// already drawn are the deck less `counts_key`, so they need no place in
+/// the key, and the memoized lengths count from the state on.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 struct StateKey {
     counts_key: u64,
     total: u16,
-    run_len: u8,
 }
 
-/// A joint distribution of terminal outcomes: total → run length → probability
-type Dist = BTreeMap<u32, BTreeMap<u32, f64>>;
+/// Probabilities by run length for one total, sorted by run length. Most
+/// totals end on only a few run lengths, so those are kept inline and the
+/// list moves to the heap only when it outgrows them.
+type LengthDist = SmallVec<[(u8, Prob); 4]>;
+
+/// A joint distribution of terminal outcomes: total → cards still to draw
+/// → probability
+type Dist = BTreeMap<u32, LengthDist>;
 
-/// The recursive probability computation with memoization
+/// Adds `p` to the probability of `run_len` in `lengths`, keeping it sorted.
+fn add_length(lengths: &mut LengthDist, run_len: u8, p: Prob) {
+    match lengths.binary_search_by_key(&run_len, |&(len, _)| len) {
+        Ok(i) => lengths[i].1 += p,
+        Err(i) => lengths.insert(i, (run_len, p)),
+    }
+}
+
+/// The recursive probability computation with memoization. `rule` decides
+/// after each card whether the line stops. `remaining_cards` is the number
+/// of cards in `counts_key`, carried down rather than recounted per state,
+/// and `run_len` the cards drawn before it. The lengths returned count the
+/// cards still to draw.
 fn dist_from_state(
     counts_key: u64,
+    remaining_cards: u32,
     total: u32,
     run_len: u32,
-    target_sum: u32,
+    rule: &dyn StoppingRule,
+    values: &[u8; RANKS],
     memo: &mut HashMap<StateKey, Dist>,
 ) -> Dist {
     let key = StateKey {
         counts_key,
         total: total as u16,
-        run_len: run_len as u8,
     };
     if let Some(cached) = memo.get(&key) {
         return cached.clone();
     }
 
-    let counts = unpack_counts(counts_key);
-    let remaining_cards: u32 = counts.iter().map(|&c| c as u32).sum();
-
-    // Terminal condition: stop if total >= target or no cards remain
-    if total >= target_sum || remaining_cards == 0 {
+    // Terminal condition: no cards remain. A single point, not worth a
+    // memo entry.
+    if remaining_cards == 0 {
         let mut d = Dist::new();
-        d.entry(total)
-            .or_insert_with(BTreeMap::new)
-            .insert(run_len, 1.0);
-        memo.insert(key, d.clone());
+        add_length(d.entry(total).or_default(), 0, 1.0);
         return d;
     }
 
     let mut result: Dist = Dist::new();
+    // Whether some card leaves the line going; see the note at the insert.
+    let mut recursed = false;
 
-    for (rank_index, &count_u8) in counts.iter().enumerate() {
-        let count = count_u8 as u32;
+    // The counts stay packed: each rank is read and decremented in place
+    // rather than unpacked to an array and repacked per draw.
+    for (rank_index, &rank_value) in values.iter().enumerate() {
+        let count = count_of(counts_key, rank_index) as u32;
         if count == 0 {
             continue;
         }
-        let rank_value = (rank_index as u32) + 1;
-        let mut next_counts = counts;
-        next_counts[rank_index] -= 1;
-        let next_counts_key = pack_counts(&next_counts);
-
-        let p = (count as f64) / (remaining_cards as f64);
+        let next_total = total + rank_value as u32;
+        // A card of this rank is left, so the divisor is positive unless
+        // the carried count has drifted from `counts_key`.
+        debug_assert!(
+            remaining_cards >= count,
+            "{} cards of a rank but {} left in all: the remaining count is off",
+            count,
+            remaining_cards
+        );
+        let p = (count as Prob) / (remaining_cards as Prob);
+
+        // Terminal condition: the rule stops the line on this card
+        if rule.should_stop(next_total as u16, (run_len + 1) as u16, rank_value) {
+            add_length(result.entry(next_total).or_default(), 1, p);
+            continue;
+        }
 
-        let sub_dist =
-            dist_from_state(next_counts_key, total + rank_value, run_len + 1, target_sum, memo);
+        recursed = true;
+        let sub_dist = dist_from_state(
+            dec_rank(counts_key, rank_index),
+            remaining_cards - 1,
+            next_total,
+            run_len + 1,
+            rule,
+            values,
+            memo,
+        );
 
         // accumulate with weight p
-        for (t, sub_map) in sub_dist {
-            for (len, subp) in sub_map {
-                *result.entry(t).or_insert_with(BTreeMap::new).entry(len).or_insert(0.0) +=
-                    p * subp;
+        for (t, sub_lengths) in sub_dist {
+            let lengths = result.entry(t).or_default();
+            for (len, subp) in sub_lengths {
+                add_length(lengths, len + 1, p * subp);
             }
         }
     }
 
-    memo.insert(key, result.clone());
+    // A state from which every card stops the line is not memoized either:
+    // its distribution is read straight off the remaining counts.
+    if recursed {
+        memo.insert(key, result.clone());
+    }
     result
 }
 
-fn main() {
-    // 40-card deck: 4 of each rank 1..10
-    let initial_counts: [u8; 10] = [4u8; 10];
-    let target_sum: u32 = 31;
+/// Exact single-line distribution of the stopping total and run length,
+/// drawing until `rule` stops the line.
+pub fn distributions(deck: &Deck, rule: &dyn StoppingRule) -> Result<ExactDist, String> {
+    if deck.jokers() > 0 {
+        return Err("the packed solver does not deal jokers; use --solver shoe".to_string());
+    }
+    if deck.soft_aces() {
+        return Err("the packed solver does not count soft aces; use --solver shoe".to_string());
+    }
+    if deck.is_weighted() {
+        return Err("the packed solver does not weight the draw; use --solver shoe".to_string());
+    }
+    // Counts are packed four bits per value, so no value may exceed 15 cards.
+    if deck.counts().iter().any(|&c| c > MAX_PER_VALUE) {
+        return Err(format!(
+            "the packed solver stores counts in 4 bits and supports at most {} cards per value",
+            MAX_PER_VALUE
+        ));
+    }
+    let mut initial_counts = [0u8; RANKS];
+    for (count, &n) in initial_counts.iter_mut().zip(deck.counts().iter()) {
+        *count = n as u8;
+    }
+    if rule::max_total(rule, deck) > u16::MAX as u32 {
+        return Err(format!(
+            "the packed solver keys totals by u16 and this deck and rule can take \
+             the total past {}",
+            u16::MAX
+        ));
+    }
 
     let counts_key = pack_counts(&initial_counts);
-    let mut memo: HashMap<StateKey, Dist> = HashMap::new();
-
-    let dist = dist_from_state(counts_key, 0, 0, target_sum, &mut memo);
-
-    println!("Simplified single-line drawing (40-card deck, no face cards)");
-    println!("Stop when total >= {}", target_sum);
-    println!("Number of reachable memo states: {}", memo.len());
-    println!();
-
-    // Flatten into margipnal distributions
-    let mut total_dist: BTreeMap<u32, f64> = BTreeMap::new();
-    let mut runlen_dist: BTreeMap<u32, f64> = BTreeMap::new();
-    let mut expected_total = 0.0;
-    let mut expected_runlen = 0.0;
-
+    // Totals and run lengths follow from the cards drawn, so this reserves
+    // one slot per state visited; the states left out of the memo leave a
+    // little to spare.
+    let mut memo: HashMap<StateKey, Dist> =
+        HashMap::with_capacity(rule::reachable_states(rule, deck).unwrap_or(0));
+
+    let dist = dist_from_state(counts_key, deck.len() as u32, 0, 0, rule, &deck.values(), &mut memo);
+
+    // Flatten into marginal distributions
+    let mut result = ExactDist {
+        scores: BTreeMap::new(),
+        lengths: BTreeMap::new(),
+        states: memo.len(),
+    };
     for (t, sub_map) in &dist {
         for (len, p) in sub_map {
-            *total_dist.entry(*t).or_insert(0.0) += p;
-            *runlen_dist.entry(*len).or_insert(0.0) += ;
-            expected_total += (*t as f64) * p;
-            expected_runlen += (*len as f64) * p;
+            *result.scores.entry(*t as u16).or_insert(0.0) += dist::to_f64(*p);
+            *result.lengths.entry(*len as u16).or_insert(0.0) += dist::to_f64(*p);
         }
     }
-
-    println!("Terminal total distribution:");
-    for (t, p) in &total_dist {
-        println!("{:3} -> {:.12}", t, p);
-    }
-    println!("\nExpected terminal total = {:.12}", expected_total);
-
-    println!("\nRun length distribution:");
-    for (len, p) in &runlen_dist {
-        println!("{:3} cards -> {:.12}", len, p);
-    }
-    println!("\nExpected run length = {:.12}", expected_runlen);
+    Ok(result)
 }
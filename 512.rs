@@ -1,45 +1,219 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use crate::dist::{self, ExactDist, Prob};
+use crate::deck::{Deck, JokerValue, RANKS};
+use crate::rule::{self, SoftAces, StoppingRule};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-// The Memo key is (current_sum, deck_counts).
+// The Memo key is (current_sum, packed deck_counts); see `KeyLayout`.
 // The value is a map of outcomes: {(final_score, cards_drawn_from_this_point) -> probability}
-type DeckCounts = [u16; 10];
-type Memo = HashMap<(u16, DeckCounts), HashMap<(u16, u16), f64>>;
+// Lengths are u16: every card scores at least a point, so a row is never
+// longer than its total, which `key_layout` keeps within a u16.
+type DeckCounts = [u16; SLOTS];
+type Outcomes = HashMap<(u16, u16), Prob>;
 
-/// Corrected recursive solver.
+/// Slots of `DeckCounts`: one per rank, as in `deck`, then the jokers.
+const SLOTS: usize = RANKS + 1;
+const JOKER_SLOT: usize = RANKS;
+
+/// How a card from each slot of `DeckCounts` scores: the ranks by their
+/// values, the jokers by `joker`, which may depend on the total, and the
+/// aces as `soft` says if given. Also how likely it is to be drawn, by
+/// `weights` if the draw is weighted.
+#[derive(Clone, Copy)]
+struct Scoring {
+    values: [u8; RANKS],
+    joker: JokerValue,
+    soft: Option<SoftAces>,
+    /// Points of the whole deck, counting every ace low. With soft aces
+    /// and no jokers, the cards drawn score this less the cards left, so a
+    /// total above that holds an ace counted high.
+    points: u32,
+    /// Draw weight of a card from each slot, the jokers weighing 1; `None`
+    /// for a fair draw.
+    weights: Option<[Prob; SLOTS]>,
+}
+
+impl Scoring {
+    fn new(deck: &Deck, rule: &dyn StoppingRule) -> Self {
+        Scoring {
+            values: deck.values(),
+            joker: deck.joker_value(),
+            soft: SoftAces::of(deck, rule),
+            points: ranked_points(&deck.counts(), &deck.values()),
+            weights: deck.is_weighted().then(|| {
+                let mut weights = [1.0; SLOTS];
+                for (slot, &weight) in weights.iter_mut().zip(&deck.weights()) {
+                    *slot = weight as Prob;
+                }
+                weights
+            }),
+        }
+    }
+
+    /// The chance the next card comes from `slot` with `counts` left, of
+    /// `cards_remaining` in all: its share of the cards, or of their weight
+    /// when the draw is weighted.
+    fn chance(&self, slot: usize, counts: &DeckCounts, cards_remaining: u16) -> Prob {
+        match &self.weights {
+            None => counts[slot] as Prob / cards_remaining as Prob,
+            Some(weights) => {
+                let total: Prob = counts.iter().zip(weights).map(|(&c, &w)| c as Prob * w).sum();
+                counts[slot] as Prob * weights[slot] / total
+            }
+        }
+    }
+
+    /// The total once a card from `slot` lands on `sum` with `counts` left
+    /// before the draw, and the points the card scores.
+    fn draw(
+        &self,
+        slot: usize,
+        sum: u16,
+        counts: &DeckCounts,
+        rule: &dyn StoppingRule,
+    ) -> (u16, u8) {
+        let points = self.points(slot, sum, rule);
+        let Some(soft) = self.soft else {
+            return (sum + points as u16, points);
+        };
+        let high = sum as u32 > self.points - ranked_points(&counts[..RANKS], &self.values);
+        (soft.draw(sum, high, points).0, points)
+    }
+
+    /// The points a card from `slot` adds to a row holding `sum`.
+    fn points(&self, slot: usize, sum: u16, rule: &dyn StoppingRule) -> u8 {
+        match slot {
+            JOKER_SLOT => self.joker.points(sum, rule),
+            rank => self.values[rank],
+        }
+    }
+}
+
+/// Points of the ranked cards `counts`, scoring `values`.
+fn ranked_points(counts: &[u16], values: &[u8; RANKS]) -> u32 {
+    counts.iter().zip(values).map(|(&c, &v)| c as u32 * v as u32).sum()
+}
+
+/// The cards of `deck` by slot.
+fn slot_counts(deck: &Deck) -> DeckCounts {
+    let mut counts = [0; SLOTS];
+    counts[..RANKS].copy_from_slice(&deck.counts());
+    counts[JOKER_SLOT] = deck.jokers();
+    counts
+}
+
+/// Where each rank's count sits in a packed `u64` key, as in the packed
+/// solver but sized per deck: a rank gets just the bits its starting count
+/// needs, so the 96 tens of a six-deck shoe (`Deck::from_standard_decks(6)`)
+/// take 7 bits and its 24 of each other rank 5. The 128 tens of an eight-deck
+/// shoe take 8 and its 32 of each other rank 6, 62 bits in all.
+#[derive(Clone)]
+struct KeyLayout {
+    shifts: [u32; SLOTS],
+}
+
+impl KeyLayout {
+    /// The layout for decks dealt from `counts`, or `None` if their counts
+    /// need more than 64 bits between them.
+    fn new(counts: &DeckCounts) -> Option<Self> {
+        let mut shifts = [0; SLOTS];
+        let mut bits = 0;
+        for (shift, &count) in shifts.iter_mut().zip(counts) {
+            *shift = bits;
+            bits += u16::BITS - count.leading_zeros();
+        }
+        (bits <= u64::BITS).then_some(KeyLayout { shifts })
+    }
+
+    fn pack(&self, counts: &DeckCounts) -> u64 {
+        counts
+            .iter()
+            .zip(&self.shifts)
+            .fold(0, |key, (&count, &shift)| key | (count as u64) << shift)
+    }
+}
+
+// Each state's outcomes are built once and shared by the memo and every
+// caller that reaches the state, rather than copied out on each visit.
+struct Memo {
+    layout: KeyLayout,
+    outcomes: HashMap<(u16, u64), Rc<Outcomes>>,
+}
+
+/// Corrected recursive solver. After each card `rule` decides whether the
+/// row stops; `drawn` cards have been dealt before this state and
+/// `total_cards_remaining` are left in `counts`, both carried down the
+/// recursion rather than recounted at every state. The memo is
+/// only valid for one deck, rule and set of values, so use a fresh one per run.
 fn solve(
     sum: u16,
     counts: DeckCounts,
+    total_cards_remaining: u16,
+    drawn: u16,
+    rule: &dyn StoppingRule,
+    scoring: &Scoring,
     memo: &mut Memo,
-) -> HashMap<(u16, u16), f64> {
-    // --- Corrected Base Case ---
-    // If the sum is over 30, the game is already over.
-    // It takes 0 more cards to finish from this point. The probability is 1.0.
-    if sum > 30 {
-        return HashMap::from([((sum, 0), 1.0)]);
+) -> Rc<Outcomes> {
+    if let Some(outcomes) = single_rank_outcomes(sum, &counts, drawn, rule, scoring) {
+        return Rc::new(outcomes);
     }
 
     // Memoization check
-    if let Some(memoized_result) = memo.get(&(sum, counts)) {
-        return memoized_result.clone();
+    let key = (sum, memo.layout.pack(&counts));
+    if let Some(memoized_result) = memo.outcomes.get(&key) {
+        return Rc::clone(memoized_result);
     }
 
-    let total_cards_remaining = counts.iter().sum::<u16>();
+    // Out of cards before the rule stops the row: it ends on its total.
     if total_cards_remaining == 0 {
-        return HashMap::new();
+        return Rc::new(Outcomes::from([((sum, 0), 1.0)]));
     }
 
-    let mut all_outcomes: HashMap<(u16, u16), f64> = HashMap::new();
+    let mut all_outcomes = Outcomes::new();
+    // Whether some card leaves the row going; see the note at the insert.
+    let mut recursed = false;
 
-    for i in 0..10 {
+    for i in 0..SLOTS {
         if counts[i] > 0 {
-            let card_value = (i + 1) as u16;
-            let prob_of_drawing_card = counts[i] as f64 / total_cards_remaining as f64;
+            let (next_sum, card_value) = scoring.draw(i, sum, &counts, rule);
+            // A card of this rank is left, so the divisor is positive
+            // unless the carried count has drifted from `counts`.
+            debug_assert!(
+                total_cards_remaining >= counts[i],
+                "{} cards of a rank but {} left in all: the remaining count is off",
+                counts[i],
+                total_cards_remaining
+            );
+            let prob_of_drawing_card = scoring.chance(i, &counts, total_cards_remaining);
+
+            // --- Corrected Base Case ---
+            // If this card stops the row, the game is over: it took exactly
+            // one more card to finish from this point.
+            if rule.should_stop(next_sum, drawn + 1, card_value) {
+                *all_outcomes.entry((next_sum, 1)).or_insert(0.0) += prob_of_drawing_card;
+                continue;
+            }
 
             let mut next_counts = counts;
             next_counts[i] -= 1;
+            recursed = true;
 
-            let sub_outcomes = solve(sum + card_value, next_counts, memo);
+            let sub_outcomes = solve(
+                next_sum,
+                next_counts,
+                total_cards_remaining - 1,
+                drawn + 1,
+                rule,
+                scoring,
+                memo,
+            );
+            // A state has at least as many outcomes as any state it leads to,
+            // so the first one sizes the map and spares most of its rehashing.
+            if all_outcomes.is_empty() {
+                all_outcomes.reserve(sub_outcomes.len());
+            }
 
             // --- Corrected Recursive Step ---
             for ((final_score, cards_to_finish), sub_prob) in sub_outcomes.iter() {
@@ -53,50 +227,252 @@ fn solve(
         }
     }
 
-    // Memoize and return
-    memo.insert((sum, counts), all_outcomes.clone());
+    // Memoize and return. A state from which every card ends the row is
+    // left out: its outcomes are read straight off the remaining counts.
+    let all_outcomes = Rc::new(all_outcomes);
+    if recursed {
+        memo.outcomes.insert(key, Rc::clone(&all_outcomes));
+    }
     all_outcomes
 }
 
-fn main() {
-    let start_time = Instant::now();
-    let mut memo: Memo = HashMap::new();
-    let mut initial_deck: DeckCounts = [24; 10];
-    initial_deck[9] = 96;
-
-    // The initial call to solve(0, ...) will return the final scores and total game lengths.
-    let results = solve(0, initial_deck, &mut memo);
-
-    let mut score_probs: HashMap<u16, f64> = HashMap::new();
-    let mut length_probs: HashMap<u16, f64> = HashMap::new();
-
-    for ((score, length), prob) in results.iter() {
-        *score_probs.entry(*score).or_insert(0.0) += prob;
-        *length_probs.entry(*length).or_insert(0.0) += prob;
-    }
-    
-    // --- Results Display ---
-    println!("--- Score Distribution (Corrected) ---");
-    let mut sorted_scores: Vec<_> = score_probs.into_iter().collect();
-    sorted_scores.sort_by_key(|&(score, _)| score);
-    let mut total_prob_score = 0.0;
-    for (score, prob) in sorted_scores {
-        println!("Score: {} | Probability: {:>9.6}%", score, prob * 100.0);
-        total_prob_score += prob;
-    }
-    println!("--------------------------------------");
-    println!("Total Probability: {:.6}%", total_prob_score * 100.0);
-    
-    println!("\n--- Length Distribution (Corrected) ---");
-    let mut sorted_lengths: Vec<_> = length_probs.into_iter().collect();
-    sorted_lengths.sort_by_key(|&(len, _)| len);
-    let mut total_prob_length = 0.0;
-    for (length, prob) in sorted_lengths {
-        println!("Length: {} | Probability: {:>9.6}%", length, prob * 100.0);
-        total_prob_length += prob;
-    }
-    println!("---------------------------------------");
-    println!("Total Probability: {:.6}%", total_prob_length * 100.0);
-
-    println!("\nCalculation finished in {:?}", start_time.elapsed());
-}
\ No newline at end of file
+/// The outcomes of a deck holding cards of a single rank, if it does. Every
+/// draw is then worth the same, so the row ends on the first card the rule
+/// stops at, with certainty, or on the last card if the rule never stops
+/// it, as the recursion would. Such states are not memoized. Jokers and
+/// soft aces are left to the recursion, since what they add may change
+/// with the total.
+fn single_rank_outcomes(
+    sum: u16,
+    counts: &DeckCounts,
+    drawn: u16,
+    rule: &dyn StoppingRule,
+    scoring: &Scoring,
+) -> Option<Outcomes> {
+    let mut ranks = counts.iter().enumerate().filter(|&(_, &count)| count > 0);
+    let (rank, &count) = ranks.next()?;
+    if ranks.next().is_some() || rank == JOKER_SLOT {
+        return None;
+    }
+    if scoring.soft.is_some_and(|soft| scoring.values[rank] == soft.ace) {
+        return None;
+    }
+    let card_value = scoring.values[rank];
+    let mut outcomes = Outcomes::new();
+    for taken in 1..=count {
+        let next_sum = sum + taken * card_value as u16;
+        // The row stops here, or with the last card if the rule never does.
+        if rule.should_stop(next_sum, drawn + taken, card_value) || taken == count {
+            outcomes.insert((next_sum, taken), 1.0);
+            break;
+        }
+    }
+    Some(outcomes)
+}
+
+/// Checks that `deck` and `rule` fit the solver's integers and returns the
+/// memo key layout for the deck.
+fn key_layout(deck: &Deck, rule: &dyn StoppingRule) -> Result<KeyLayout, String> {
+    // A soft ace's count is read off the points drawn, which a joker hides.
+    if deck.soft_aces() && deck.jokers() > 0 {
+        return Err("the shoe solver cannot deal jokers and soft aces together".to_string());
+    }
+    if rule::max_total(rule, deck) > u16::MAX as u32 {
+        return Err(format!(
+            "the shoe solver sums points in a u16, and this deck and rule can take \
+             the total past {}",
+            u16::MAX
+        ));
+    }
+    KeyLayout::new(&slot_counts(deck)).ok_or_else(|| {
+        "the shoe solver packs the card counts into 64 bits, and this deck has too many \
+         cards of too many values"
+            .to_string()
+    })
+}
+
+/// Exact score and length distributions of one row for large shoes, solved
+/// by memoized recursion over the remaining deck composition.
+pub fn distributions(deck: &Deck, rule: &dyn StoppingRule) -> Result<ExactDist, String> {
+    let layout = key_layout(deck, rule)?;
+    // Reserving every state up front avoids rehashing the memo as it grows.
+    let mut memo = Memo {
+        layout,
+        outcomes: HashMap::with_capacity(rule::reachable_states(rule, deck).unwrap_or(0)),
+    };
+    let scoring = Scoring::new(deck, rule);
+    let results = solve(0, slot_counts(deck), deck.len() as u16, 0, rule, &scoring, &mut memo);
+
+    Ok(flatten(&results, memo.outcomes.len()))
+}
+
+/// Marginal score and length distributions of the joint `outcomes`. The
+/// outcomes are summed in key order rather than the map's, which differs
+/// from run to run, so the last digits of the marginals do not.
+fn flatten(outcomes: &Outcomes, states: usize) -> ExactDist {
+    let mut dist = ExactDist {
+        scores: BTreeMap::new(),
+        lengths: BTreeMap::new(),
+        states,
+    };
+    let mut sorted: Vec<_> = outcomes.iter().collect();
+    sorted.sort_unstable_by_key(|&(&key, _)| key);
+    for ((score, length), prob) in sorted {
+        *dist.scores.entry(*score).or_insert(0.0) += dist::to_f64(*prob);
+        *dist.lengths.entry(*length).or_insert(0.0) += dist::to_f64(*prob);
+    }
+    dist
+}
+
+/// Subtrees `distributions_sharded` aims to give each thread, so a thread
+/// that drew small ones can pick up more while the others finish.
+const SHARDS_PER_THREAD: usize = 8;
+
+/// One subtree of the sharded solver: the row once its opening cards are
+/// dealt, reached with probability `prob`.
+struct Shard {
+    sum: u16,
+    counts: DeckCounts,
+    cards_remaining: u16,
+    drawn: u16,
+    prob: Prob,
+}
+
+/// Deals opening cards one layer at a time until there are at least `want`
+/// subtrees still drawing, merging orders of the same cards into one shard.
+/// Rows the rule stops during the opening are added to `stopped`.
+fn split(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    scoring: &Scoring,
+    want: usize,
+    stopped: &mut Outcomes,
+) -> Vec<Shard> {
+    let mut shards = vec![Shard {
+        sum: 0,
+        counts: slot_counts(deck),
+        cards_remaining: deck.len() as u16,
+        drawn: 0,
+        prob: 1.0,
+    }];
+    while shards.len() < want && shards.iter().all(|shard| shard.cards_remaining > 0) {
+        let mut next: HashMap<(u16, DeckCounts), Shard> = HashMap::new();
+        for shard in &shards {
+            for i in 0..SLOTS {
+                if shard.counts[i] == 0 {
+                    continue;
+                }
+                let (next_sum, card_value) = scoring.draw(i, shard.sum, &shard.counts, rule);
+                debug_assert!(
+                    shard.cards_remaining >= shard.counts[i],
+                    "{} cards of a rank but {} left in all: the remaining count is off",
+                    shard.counts[i],
+                    shard.cards_remaining
+                );
+                let prob = shard.prob * scoring.chance(i, &shard.counts, shard.cards_remaining);
+                if rule.should_stop(next_sum, shard.drawn + 1, card_value) {
+                    *stopped.entry((next_sum, shard.drawn + 1)).or_insert(0.0) += prob;
+                    continue;
+                }
+                let mut counts = shard.counts;
+                counts[i] -= 1;
+                next.entry((next_sum, counts))
+                    .or_insert(Shard {
+                        sum: next_sum,
+                        counts,
+                        cards_remaining: shard.cards_remaining - 1,
+                        drawn: shard.drawn + 1,
+                        prob: 0.0,
+                    })
+                    .prob += prob;
+            }
+        }
+        shards = next.into_values().collect();
+        // Fixed order, so the next layer and the solved shards are summed
+        // the same way on every run.
+        shards.sort_unstable_by_key(|shard| (shard.sum, shard.counts));
+        if shards.is_empty() {
+            break;
+        }
+    }
+    shards
+}
+
+/// `distributions` spread over `threads` threads. The opening cards split
+/// the row into independent subtrees, which the threads take in turn, each
+/// with a memo of its own: no locks, at the price of solving the states
+/// that subtrees share once per thread that meets them. The states reported
+/// are the sum of the threads' memos. Sums of probabilities are taken in a
+/// different order than the serial solver's, so the results agree with it
+/// to rounding error rather than bit for bit.
+///
+/// That price is high. Different openings soon reach the same compositions,
+/// so each thread ends up solving most of the serial memo: on the presets
+/// two threads visit about twice the serial states and sixteen 8 to 14
+/// times as many. Wall time is then at best the serial time, whatever the
+/// core count. Parallelism that pays would have to share the memo, for
+/// instance by solving all states with the same number of cards drawn
+/// together, deepest layer first.
+pub fn distributions_sharded(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    threads: usize,
+) -> Result<ExactDist, String> {
+    let layout = key_layout(deck, rule)?;
+    let scoring = Scoring::new(deck, rule);
+    let mut outcomes = Outcomes::new();
+    let shards = split(deck, rule, &scoring, threads * SHARDS_PER_THREAD, &mut outcomes);
+
+    // Each shard's outcomes, measured from the row's first card, in shard order.
+    let mut solved: Vec<Vec<((u16, u16), Prob)>> = vec![Vec::new(); shards.len()];
+    let next_shard = AtomicUsize::new(0);
+    let mut states = 0;
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(shards.len()))
+            .map(|_| {
+                let (layout, shards, next_shard) = (layout.clone(), &shards, &next_shard);
+                scope.spawn(move || {
+                    let mut memo = Memo {
+                        layout,
+                        outcomes: HashMap::new(),
+                    };
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next_shard.fetch_add(1, Ordering::Relaxed);
+                        let Some(shard) = shards.get(index) else {
+                            break;
+                        };
+                        let sub_outcomes = solve(
+                            shard.sum,
+                            shard.counts,
+                            shard.cards_remaining,
+                            shard.drawn,
+                            rule,
+                            &scoring,
+                            &mut memo,
+                        );
+                        let weighted = sub_outcomes
+                            .iter()
+                            .map(|(&(score, length), &p)| ((score, length + shard.drawn), shard.prob * p))
+                            .collect();
+                        done.push((index, weighted));
+                    }
+                    (done, memo.outcomes.len())
+                })
+            })
+            .collect();
+        for worker in workers {
+            let (done, worker_states) = worker.join().expect("solver thread panicked");
+            for (index, weighted) in done {
+                solved[index] = weighted;
+            }
+            states += worker_states;
+        }
+    });
+    for weighted in solved {
+        for (key, p) in weighted {
+            *outcomes.entry(key).or_insert(0.0) += p;
+        }
+    }
+    Ok(flatten(&outcomes, states))
+}
@@ -1,41 +1,107 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use crate::dist::{self, ExactDist, Prob};
+use crate::deck::{Deck, RANKS};
+use crate::rule::{self, StoppingRule};
+use std::collections::{BTreeMap, HashMap};
+#[cfg(not(feature = "arena"))]
+use std::rc::Rc;
 
-// The Memo key is (current_sum, deck_counts).
+// The Memo key is (current_sum, deck_id), the id standing for the deck counts.
 // The value is a map of outcomes: {(final_score, cards_drawn_from_this_point) -> probability}
-type DeckCounts = [u8; 10];
-type Memo = HashMap<(u8, DeckCounts), HashMap<(u8, u8), f64>>;
+// Lengths are u8: `distributions` takes at most 255 cards, so no row is longer.
+type DeckCounts = [u8; RANKS];
+type Outcomes = HashMap<(u8, u8), Prob>;
+type StateKey = (u8, u32);
 
-/// Corrected recursive solver.
+/// Numbers each distinct deck composition in the order it is first seen,
+/// so the memo keys on a `u32` instead of the whole array.
+struct Interner(HashMap<DeckCounts, u32>);
+
+impl Interner {
+    fn with_capacity(capacity: usize) -> Self {
+        Interner(HashMap::with_capacity(capacity))
+    }
+
+    /// The id of `counts`, assigning the next free one if it is new.
+    fn id(&mut self, counts: DeckCounts) -> u32 {
+        let next = self.0.len() as u32;
+        *self.0.entry(counts).or_insert(next)
+    }
+}
+
+// Each state's outcomes are built once and shared by the memo and every
+// caller that reaches the state, rather than copied out on each visit.
+#[cfg(not(feature = "arena"))]
+struct Memo {
+    ids: Interner,
+    outcomes: HashMap<StateKey, Rc<Outcomes>>,
+}
+
+/// Corrected recursive solver. After each card `rule` decides whether the
+/// row stops; `drawn` cards have been dealt before this state and
+/// `total_cards_remaining` are left in `counts`, both carried down the
+/// recursion rather than recounted at every state. The memo is
+/// only valid for one deck, rule and set of values, so use a fresh one per run.
+#[cfg(not(feature = "arena"))]
 fn solve(
     sum: u8,
     counts: DeckCounts,
+    total_cards_remaining: u8,
+    drawn: u16,
+    rule: &dyn StoppingRule,
+    values: &[u8; RANKS],
     memo: &mut Memo,
-) -> HashMap<(u8, u8), f64> {
-    if sum > 30 {
-        return HashMap::from([((sum, 0), 1.0)]);
-    }
-
-    if let Some(memoized_result) = memo.get(&(sum, counts)) {
-        return memoized_result.clone();
+) -> Rc<Outcomes> {
+    let key = (sum, memo.ids.id(counts));
+    if let Some(memoized_result) = memo.outcomes.get(&key) {
+        return Rc::clone(memoized_result);
     }
 
-    let total_cards_remaining = counts.iter().sum::<u8>();
+    // Out of cards before the rule stops the row: it ends on its total.
     if total_cards_remaining == 0 {
-        return HashMap::new();
+        return Rc::new(Outcomes::from([((sum, 0), 1.0)]));
     }
 
-    let mut all_outcomes: HashMap<(u8, u8), f64> = HashMap::new();
+    let mut all_outcomes = Outcomes::new();
+    // Whether some card leaves the row going; see the note at the insert.
+    let mut recursed = false;
 
-    for i in 0..10 {
+    for i in 0..RANKS {
         if counts[i] > 0 {
-            let card_value = (i + 1) as u8;
-            let prob_of_drawing_card = counts[i] as f64 / total_cards_remaining as f64;
+            let card_value = values[i];
+            // A card of this rank is left, so the divisor is positive
+            // unless the carried count has drifted from `counts`.
+            debug_assert!(
+                total_cards_remaining >= counts[i],
+                "{} cards of a rank but {} left in all: the remaining count is off",
+                counts[i],
+                total_cards_remaining
+            );
+            let prob_of_drawing_card = counts[i] as Prob / total_cards_remaining as Prob;
+            let next_sum = sum + card_value;
+
+            if rule.should_stop(next_sum as u16, drawn + 1, card_value) {
+                *all_outcomes.entry((next_sum, 1)).or_insert(0.0) += prob_of_drawing_card;
+                continue;
+            }
 
             let mut next_counts = counts;
             next_counts[i] -= 1;
+            recursed = true;
 
-            let sub_outcomes = solve(sum + card_value, next_counts, memo);
+            let sub_outcomes = solve(
+                next_sum,
+                next_counts,
+                total_cards_remaining - 1,
+                drawn + 1,
+                rule,
+                values,
+                memo,
+            );
+            // A state has at least as many outcomes as any state it leads to,
+            // so the first one sizes the map and spares most of its rehashing.
+            if all_outcomes.is_empty() {
+                all_outcomes.reserve(sub_outcomes.len());
+            }
 
             for ((final_score, cards_to_finish), sub_prob) in sub_outcomes.iter() {
                 let total_prob = prob_of_drawing_card * sub_prob;
@@ -46,58 +112,171 @@ fn solve(
         }
     }
 
-    memo.insert((sum, counts), all_outcomes.clone());
+    // A state from which every card ends the row is not memoized: its
+    // outcomes are read straight off the remaining counts, which costs
+    // about as much as the lookup, and such states are a large share of
+    // the memo.
+    let all_outcomes = Rc::new(all_outcomes);
+    if recursed {
+        memo.outcomes.insert(key, Rc::clone(&all_outcomes));
+    }
     all_outcomes
 }
 
-fn main() {
-    let start_time = Instant::now();
-    let mut memo: Memo = HashMap::new();
-    let mut initial_deck: DeckCounts = [0; 10];
-
-    for i in 0..7 {
-        initial_deck[i] = 4
-    }
-    initial_deck[9] = 12;
-
-    let results = solve(0, initial_deck, &mut memo);
-
-    let mut score_probs: HashMap<u8, f64> = HashMap::new();
-    let mut length_probs: HashMap<u8, f64> = HashMap::new();
-
-    for ((score, length), prob) in results.iter() {
-        *score_probs.entry(*score).or_insert(0.0) += prob;
-        *length_probs.entry(*length).or_insert(0.0) += prob;
-    }
-    
-    // --- Results Display ---
-    println!("--- Score Distribution (Corrected) ---");
-    let mut sorted_scores: Vec<_> = score_probs.into_iter().collect();
-    sorted_scores.sort_by_key(|&(score, _)| score);
-    let mut total_prob_score = 0.0;
-    let mut expected_score = 0.0;
-    for (score, prob) in sorted_scores {
-        println!("Score: {} | Probability: {:>9.6}%", score, prob * 100.0);
-        total_prob_score += prob;
-        expected_score += score as f64 * prob;
-    }
-    println!("--------------------------------------");
-    println!("Total Probability: {:.6}%", total_prob_score * 100.0);
-    println!("Average Final Score: {:.6}", expected_score);
-
-    println!("\n--- Length Distribution (Corrected) ---");
-    let mut sorted_lengths: Vec<_> = length_probs.into_iter().collect();
-    sorted_lengths.sort_by_key(|&(len, _)| len);
-    let mut total_prob_length = 0.0;
-    let mut expected_length = 0.0;
-    for (length, prob) in sorted_lengths {
-        println!("Length: {} | Probability: {:>9.6}%", length, prob * 100.0);
-        total_prob_length += prob;
-        expected_length += length as f64 * prob;
-    }
-    println!("---------------------------------------");
-    println!("Total Probability: {:.6}%", total_prob_length * 100.0);
-    println!("Average Run Length: {:.6}", expected_length);
-
-    println!("\nCalculation finished in {:?}", start_time.elapsed());
+// With the `arena` feature the memo keeps each state's outcomes as a slice
+// in a bump arena instead of a map of its own: one allocation region for the
+// whole run instead of one per state, and memo hits are returned by
+// reference rather than cloned. The slices borrow the arena, hence the
+// separate solver.
+#[cfg(feature = "arena")]
+type OutcomeSlice<'a> = &'a [((u8, u8), Prob)];
+#[cfg(feature = "arena")]
+struct ArenaMemo<'a> {
+    ids: Interner,
+    outcomes: HashMap<StateKey, OutcomeSlice<'a>>,
+}
+
+/// `solve` with its memo values in `arena`; see `ArenaMemo`.
+#[cfg(feature = "arena")]
+#[allow(clippy::too_many_arguments)]
+fn solve_in_arena<'a>(
+    sum: u8,
+    counts: DeckCounts,
+    total_cards_remaining: u8,
+    drawn: u16,
+    rule: &dyn StoppingRule,
+    values: &[u8; RANKS],
+    arena: &'a bumpalo::Bump,
+    memo: &mut ArenaMemo<'a>,
+) -> OutcomeSlice<'a> {
+    let key = (sum, memo.ids.id(counts));
+    if let Some(&memoized_result) = memo.outcomes.get(&key) {
+        return memoized_result;
+    }
+
+    // Out of cards before the rule stops the row: it ends on its total.
+    if total_cards_remaining == 0 {
+        return arena.alloc([((sum, 0), 1.0)]);
+    }
+
+    let mut all_outcomes = Outcomes::new();
+
+    for i in 0..RANKS {
+        if counts[i] > 0 {
+            let card_value = values[i];
+            // A card of this rank is left, so the divisor is positive
+            // unless the carried count has drifted from `counts`.
+            debug_assert!(
+                total_cards_remaining >= counts[i],
+                "{} cards of a rank but {} left in all: the remaining count is off",
+                counts[i],
+                total_cards_remaining
+            );
+            let prob_of_drawing_card = counts[i] as Prob / total_cards_remaining as Prob;
+            let next_sum = sum + card_value;
+
+            if rule.should_stop(next_sum as u16, drawn + 1, card_value) {
+                *all_outcomes.entry((next_sum, 1)).or_insert(0.0) += prob_of_drawing_card;
+                continue;
+            }
+
+            let mut next_counts = counts;
+            next_counts[i] -= 1;
+
+            let sub_outcomes = solve_in_arena(
+                next_sum,
+                next_counts,
+                total_cards_remaining - 1,
+                drawn + 1,
+                rule,
+                values,
+                arena,
+                memo,
+            );
+            if all_outcomes.is_empty() {
+                all_outcomes.reserve(sub_outcomes.len());
+            }
+
+            for &((final_score, cards_to_finish), sub_prob) in sub_outcomes {
+                let total_prob = prob_of_drawing_card * sub_prob;
+                *all_outcomes
+                    .entry((final_score, cards_to_finish + 1))
+                    .or_insert(0.0) += total_prob;
+            }
+        }
+    }
+
+    let stored = &*arena.alloc_slice_fill_iter(all_outcomes);
+    memo.outcomes.insert(key, stored);
+    stored
+}
+
+/// Exact score and length distributions of one row, solved by memoized
+/// recursion over the remaining deck composition.
+pub fn distributions(deck: &Deck, rule: &dyn StoppingRule) -> Result<ExactDist, String> {
+    if deck.jokers() > 0 {
+        return Err("the memo solver does not deal jokers; use --solver shoe".to_string());
+    }
+    if deck.soft_aces() {
+        return Err("the memo solver does not count soft aces; use --solver shoe".to_string());
+    }
+    if deck.is_weighted() {
+        return Err("the memo solver does not weight the draw; use --solver shoe".to_string());
+    }
+    if deck.len() > u8::MAX as usize {
+        return Err(format!(
+            "the memo solver counts cards in a u8 and supports at most {} cards; \
+             use --solver shoe for larger decks",
+            u8::MAX
+        ));
+    }
+    if rule::max_total(rule, deck) > u8::MAX as u32 {
+        return Err(format!(
+            "the memo solver sums points in a u8, and this deck and rule can take \
+             the total past {}; use --solver shoe",
+            u8::MAX
+        ));
+    }
+    let mut initial_deck: DeckCounts = [0; RANKS];
+    for (count, &n) in initial_deck.iter_mut().zip(deck.counts().iter()) {
+        *count = n as u8;
+    }
+
+    // Reserving every state up front avoids rehashing the memo as it grows.
+    let capacity = rule::reachable_states(rule, deck).unwrap_or(0);
+    let (cards, values) = (deck.len() as u8, deck.values());
+
+    #[cfg(not(feature = "arena"))]
+    let (mut results, states) = {
+        let mut memo = Memo {
+            ids: Interner::with_capacity(capacity),
+            outcomes: HashMap::with_capacity(capacity),
+        };
+        let results = solve(0, initial_deck, cards, 0, rule, &values, &mut memo);
+        (results.iter().map(|(&key, &prob)| (key, prob)).collect::<Vec<_>>(), memo.outcomes.len())
+    };
+    #[cfg(feature = "arena")]
+    let (mut results, states) = {
+        let arena = bumpalo::Bump::new();
+        let mut memo = ArenaMemo {
+            ids: Interner::with_capacity(capacity),
+            outcomes: HashMap::with_capacity(capacity),
+        };
+        let results = solve_in_arena(0, initial_deck, cards, 0, rule, &values, &arena, &mut memo);
+        (results.to_vec(), memo.outcomes.len())
+    };
+
+    // Summed in key order rather than the memo map's, which differs from
+    // run to run, so the last digits of the marginals do not.
+    results.sort_unstable_by_key(|&(key, _)| key);
+    let mut dist = ExactDist {
+        scores: BTreeMap::new(),
+        lengths: BTreeMap::new(),
+        states,
+    };
+    for ((score, length), prob) in results {
+        *dist.scores.entry(score as u16).or_insert(0.0) += dist::to_f64(prob);
+        *dist.lengths.entry(length as u16).or_insert(0.0) += dist::to_f64(prob);
+    }
+    Ok(dist)
 }
@@ -0,0 +1,74 @@
+//! Criterion benchmarks for the hot paths: `cargo bench`. Each group has a
+//! small-deck case that finishes in moments, for quick checks and CI
+//! (`cargo bench -- small`).
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use monte_carlo_sim::deck::{Deck, JokerValue};
+use monte_carlo_sim::game::{play_game, BatchRng};
+use monte_carlo_sim::memo_sim;
+use monte_carlo_sim::packed::{count_of, dec_rank, pack_counts, unpack_counts};
+use monte_carlo_sim::rule::SumThreshold;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Two of each value: 20 cards, solved in well under a millisecond.
+fn small_deck() -> Deck {
+    Deck::from_ranks(&[2; 10]).expect("ten ranks fit")
+}
+
+fn spanish_40() -> Deck {
+    Deck::preset("spanish-40").expect("built-in preset")
+}
+
+fn bench_play_game(c: &mut Criterion) {
+    let mut group = c.benchmark_group("play_game");
+    let rule = SumThreshold::default();
+    for (name, deck) in [("small", small_deck()), ("spanish-40", spanish_40())] {
+        let mut cards = deck.cards();
+        let mut rng = StdRng::seed_from_u64(1);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                play_game(black_box(&mut cards), &rule, JokerValue::Best, None, None, &mut rng)
+            })
+        });
+    }
+    for batch in [16, 64, 256, 1024] {
+        let mut cards = spanish_40().cards();
+        let mut rng = BatchRng::new(StdRng::seed_from_u64(1), batch);
+        group.bench_function(format!("spanish-40/batch-{}", batch), |b| {
+            b.iter(|| {
+                play_game(black_box(&mut cards), &rule, JokerValue::Best, None, None, &mut rng)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_solve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve");
+    group.sample_size(20);
+    let cases = [
+        ("small", small_deck(), SumThreshold(12)),
+        ("spanish-40", spanish_40(), SumThreshold::default()),
+    ];
+    for (name, deck, rule) in cases {
+        group.bench_function(name, |b| {
+            b.iter(|| memo_sim::distributions(black_box(&deck), &rule).expect("solvable"))
+        });
+    }
+    group.finish();
+}
+
+fn bench_pack_counts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pack_counts");
+    let counts = spanish_40().counts().map(|count| count as u8);
+    group.bench_function("small/pack", |b| b.iter(|| pack_counts(black_box(&counts))));
+    let key = pack_counts(&counts);
+    group.bench_function("small/unpack", |b| b.iter(|| unpack_counts(black_box(key))));
+    group.bench_function("small/count_of", |b| b.iter(|| count_of(black_box(key), black_box(9))));
+    group.bench_function("small/dec_rank", |b| b.iter(|| dec_rank(black_box(key), black_box(9))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_play_game, bench_solve, bench_pack_counts);
+criterion_main!(benches);
@@ -0,0 +1,452 @@
+use crate::deck::Deck;
+use crate::rule::SumThreshold;
+use crate::shoe::Outcome;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What becomes of an even-money bet, on rouge or noir, when the coup is a
+/// refait.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RefaitRule {
+    /// The player gives up half the stake and keeps the other half.
+    Half,
+    /// The stake is imprisoned until the next coup that is not a tie: it is
+    /// returned if the bet wins then, and lost if it loses or that coup is
+    /// another refait.
+    Prison,
+    /// The house takes this fraction of the stake as a commission and
+    /// returns the rest.
+    Commission(f64),
+}
+
+/// The commission of `commission` when no rate is given, a fraction.
+pub const DEFAULT_COMMISSION: f64 = 0.05;
+
+impl RefaitRule {
+    /// Every rule, in report order.
+    pub const ALL: [RefaitRule; 3] = [
+        RefaitRule::Half,
+        RefaitRule::Prison,
+        RefaitRule::Commission(DEFAULT_COMMISSION),
+    ];
+}
+
+impl fmt::Display for RefaitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefaitRule::Half => write!(f, "half lost"),
+            RefaitRule::Prison => write!(f, "en prison"),
+            RefaitRule::Commission(rate) => write!(f, "{}% commission", rate * 100.0),
+        }
+    }
+}
+
+/// Parses a `--refait` argument: `half`, `prison`, or `commission` with an
+/// optional percentage such as `commission=2.5`.
+pub fn parse_refait(arg: &str) -> Result<RefaitRule, String> {
+    match arg.split_once('=') {
+        None if arg == "half" => Ok(RefaitRule::Half),
+        None if arg == "prison" => Ok(RefaitRule::Prison),
+        None if arg == "commission" => Ok(RefaitRule::Commission(DEFAULT_COMMISSION)),
+        Some(("commission", percent)) => percent
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|p| (0.0..=100.0).contains(p))
+            .map(|p| RefaitRule::Commission(p / 100.0))
+            .ok_or_else(|| format!("'{}' is not a percentage in 0..=100", percent.trim())),
+        _ => Err(format!(
+            "expected half, prison or commission[=PERCENT], found '{}'",
+            arg
+        )),
+    }
+}
+
+/// The bets on a coup, paid even money unless `Payouts` says otherwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Bet {
+    /// The Noir row wins.
+    Noir,
+    /// The Rouge row wins.
+    Rouge,
+    /// The first card dealt has the color of the winning row.
+    Couleur,
+    /// The first card dealt has the color of the losing row.
+    Inverse,
+}
+
+impl Bet {
+    /// Every bet, in report order.
+    pub const ALL: [Bet; 4] = [Bet::Noir, Bet::Rouge, Bet::Couleur, Bet::Inverse];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bet::Noir => "Noir",
+            Bet::Rouge => "Rouge",
+            Bet::Couleur => "Couleur",
+            Bet::Inverse => "Inverse",
+        }
+    }
+
+    /// The odds of this bet from outcome probabilities indexed like
+    /// `Outcome::ALL`, paid as `payouts` says. Every value comes in both
+    /// colors equally, so the first card's color says nothing about the
+    /// totals: couleur and inverse each win half the coups that either row
+    /// wins.
+    pub fn odds(self, probs: &[f64; Outcome::ALL.len()], payouts: &Payouts) -> BetOdds {
+        let [noir, rouge, tie, refait] = *probs;
+        let (win, lose) = match self {
+            Bet::Noir => (noir, rouge),
+            Bet::Rouge => (rouge, noir),
+            Bet::Couleur | Bet::Inverse => ((noir + rouge) / 2.0, (noir + rouge) / 2.0),
+        };
+        BetOdds {
+            win,
+            lose,
+            tie,
+            refait,
+            payout: payouts.get(self),
+        }
+    }
+}
+
+/// What each bet pays on a win, as a multiple of the stake.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Payouts([f64; Bet::ALL.len()]);
+
+impl Default for Payouts {
+    /// Even money on every bet.
+    fn default() -> Self {
+        Payouts([1.0; Bet::ALL.len()])
+    }
+}
+
+impl Payouts {
+    pub fn get(&self, bet: Bet) -> f64 {
+        self.0[bet as usize]
+    }
+}
+
+impl fmt::Display for Payouts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, bet) in Bet::ALL.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}:1", bet.name(), self.get(*bet))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a `--payouts` argument: comma-separated `bet=multiple` pairs, each
+/// setting what one bet pays on a win, e.g. `couleur=0.95`. Bets not listed
+/// pay even money.
+pub fn parse_payouts(arg: &str) -> Result<Payouts, String> {
+    use clap::ValueEnum;
+    let mut payouts = Payouts::default();
+    for pair in arg.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (bet, multiple) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected bet=multiple, found '{}'", pair))?;
+        let bet = Bet::from_str(bet.trim(), true).map_err(|_| {
+            format!("'{}' is not a bet; expected noir, rouge, couleur or inverse", bet.trim())
+        })?;
+        let multiple: f64 = multiple
+            .trim()
+            .parse()
+            .ok()
+            .filter(|m: &f64| m.is_finite() && *m >= 0.0)
+            .ok_or_else(|| format!("'{}' is not a non-negative payout", multiple.trim()))?;
+        payouts.0[bet as usize] = multiple;
+    }
+    Ok(payouts)
+}
+
+/// How a coup settles a bet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Settlement {
+    Win,
+    Lose,
+    /// A tie above the refait total: the stake is returned.
+    Push,
+    /// Settled by the `RefaitRule` in play.
+    Refait,
+}
+
+impl Bet {
+    /// How a coup that ended in `outcome` settles this bet. Couleur and
+    /// inverse also need the color of the first card dealt.
+    pub fn settle(self, outcome: Outcome, first_card_black: bool) -> Settlement {
+        let noir_wins = match outcome {
+            Outcome::Noir => true,
+            Outcome::Rouge => false,
+            Outcome::Tie => return Settlement::Push,
+            Outcome::Refait => return Settlement::Refait,
+        };
+        let won = match self {
+            Bet::Noir => noir_wins,
+            Bet::Rouge => !noir_wins,
+            Bet::Couleur => noir_wins == first_card_black,
+            Bet::Inverse => noir_wins != first_card_black,
+        };
+        if won {
+            Settlement::Win
+        } else {
+            Settlement::Lose
+        }
+    }
+}
+
+/// The chances of one coup for one bet.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BetOdds {
+    pub win: f64,
+    pub lose: f64,
+    /// A tie above the refait total: the stake is returned.
+    pub tie: f64,
+    pub refait: f64,
+    /// What a win pays, as a multiple of the stake.
+    pub payout: f64,
+}
+
+impl BetOdds {
+    /// The net results of one unit staked on a coup, with their chances. An
+    /// imprisoned stake is counted as settled on the coup that imprisoned
+    /// it: returned if the bet wins the next decided coup, lost otherwise.
+    pub fn payoffs(&self, rule: RefaitRule) -> Vec<(f64, f64)> {
+        let mut payoffs = vec![(self.payout, self.win), (-1.0, self.lose), (0.0, self.tie)];
+        match rule {
+            RefaitRule::Half => payoffs.push((-0.5, self.refait)),
+            RefaitRule::Commission(rate) => payoffs.push((-rate, self.refait)),
+            RefaitRule::Prison => {
+                let freed = self.win / (self.win + self.lose + self.refait);
+                payoffs.push((0.0, self.refait * freed));
+                payoffs.push((-1.0, self.refait * (1.0 - freed)));
+            }
+        }
+        payoffs
+    }
+
+    /// What a refait costs the bettor per unit staked, on average: the
+    /// half, the commission, or the chance that the imprisoned stake is lost.
+    pub fn refait_cost(&self, rule: RefaitRule) -> f64 {
+        match rule {
+            RefaitRule::Half => 0.5,
+            RefaitRule::Commission(rate) => rate,
+            RefaitRule::Prison => 1.0 - self.win / (self.win + self.lose + self.refait),
+        }
+    }
+
+    /// The bettor's expected gain per unit staked from insuring this bet
+    /// against a refait: `premium` is paid on every coup, and a refait then
+    /// returns the whole stake instead of settling it by `rule`.
+    pub fn insurance(&self, rule: RefaitRule, premium: f64) -> f64 {
+        self.refait * self.refait_cost(rule) - premium
+    }
+
+    /// The bettor's expected gain per unit staked on one coup.
+    pub fn expected_return(&self, rule: RefaitRule) -> f64 {
+        self.payoffs(rule).iter().map(|&(net, p)| net * p).sum()
+    }
+
+    /// The house's expected gain per unit staked on one coup, a fraction.
+    pub fn house_edge(&self, rule: RefaitRule) -> f64 {
+        -self.expected_return(rule)
+    }
+
+    /// `house_edge` per decided coup: the ties that return the stake are
+    /// left out, as the edge is often quoted. A refait still counts.
+    pub fn house_edge_decided(&self, rule: RefaitRule) -> f64 {
+        self.house_edge(rule) / (1.0 - self.tie)
+    }
+
+    /// The return to player: what the bettor gets back in the long run per
+    /// unit staked, stake included, a fraction.
+    pub fn rtp(&self, rule: RefaitRule) -> f64 {
+        1.0 + self.expected_return(rule)
+    }
+
+    /// The variance of the net result of one unit staked on one coup.
+    pub fn variance(&self, rule: RefaitRule) -> f64 {
+        let mean = self.expected_return(rule);
+        self.payoffs(rule)
+            .iter()
+            .map(|&(net, p)| (net - mean).powi(2) * p)
+            .sum()
+    }
+
+    /// The Kelly fraction of the bankroll to stake on this bet.
+    pub fn kelly(&self, rule: RefaitRule) -> f64 {
+        kelly_fraction(self.expected_return(rule), self.variance(rule))
+    }
+
+    /// The mean and standard deviation of the net result of a session of
+    /// `coups` coups with `stake` on each. The coups are taken to be
+    /// independent, as they nearly are in a large shoe.
+    pub fn session(&self, rule: RefaitRule, coups: u64, stake: f64) -> (f64, f64) {
+        let n = coups as f64;
+        let mean = n * stake * self.expected_return(rule);
+        let std_dev = stake * (n * self.variance(rule)).sqrt();
+        (mean, std_dev)
+    }
+}
+
+/// The fraction of the bankroll the Kelly criterion stakes on a bet with
+/// expected gain `edge` and `variance` per unit staked, by the usual
+/// `edge / variance` approximation. A bet without a positive edge gets
+/// nothing, as every bet does off the top of the shoe.
+pub fn kelly_fraction(edge: f64, variance: f64) -> f64 {
+    if edge <= 0.0 || variance <= 0.0 {
+        return 0.0;
+    }
+    edge / variance
+}
+
+/// Outcome probabilities, indexed like `Outcome::ALL`, of a coup whose rows
+/// each stop on a total drawn from `scores` independently. That ignores the
+/// cards the first row takes from the shoe, which a six-deck shoe barely
+/// feels. A refait is a tie on `threshold + 1`.
+pub fn independent_rows(scores: &BTreeMap<u16, f64>, threshold: u16) -> [f64; Outcome::ALL.len()] {
+    let (mut noir, mut tie, mut refait) = (0.0, 0.0, 0.0);
+    let mut below = 0.0;
+    for (&total, &p) in scores {
+        // Noir wins with the lower total: against every higher Rouge total.
+        noir += p * (1.0 - below - p);
+        if total == SumThreshold(threshold).lowest_stop() {
+            refait += p * p;
+        } else {
+            tie += p * p;
+        }
+        below += p;
+    }
+    // The rows are alike, so Rouge wins as often as Noir.
+    [noir, noir, tie, refait]
+}
+
+/// `independent_rows` for rows that stop by different rules, the Noir row
+/// on a total drawn from `noir` and the Rouge row on one from `rouge`. A
+/// refait is still a tie on `threshold + 1`, the Noir row's lowest stop.
+pub fn independent_rows_apart(
+    noir: &BTreeMap<u16, f64>,
+    rouge: &BTreeMap<u16, f64>,
+    threshold: u16,
+) -> [f64; Outcome::ALL.len()] {
+    let above = |scores: &BTreeMap<u16, f64>, total: u16| {
+        scores.range(total + 1..).map(|(_, p)| p).sum::<f64>()
+    };
+    let (mut noir_wins, mut rouge_wins, mut tie, mut refait) = (0.0, 0.0, 0.0, 0.0);
+    for (&total, &p) in noir {
+        noir_wins += p * above(rouge, total);
+        let same = p * rouge.get(&total).copied().unwrap_or(0.0);
+        if total == SumThreshold(threshold).lowest_stop() {
+            refait += same;
+        } else {
+            tie += same;
+        }
+    }
+    for (&total, &p) in rouge {
+        rouge_wins += p * above(noir, total);
+    }
+    [noir_wins, rouge_wins, tie, refait]
+}
+
+/// `outcomes` with the refait chance replaced by `refait`, as computed with
+/// dependent rows by `exact_refait`, and the other outcomes scaled to fill
+/// the rest. Only the refait is known that exactly; the scaling keeps the
+/// wins and ties in the proportions the independent rows give them.
+pub fn with_refait(outcomes: [f64; Outcome::ALL.len()], refait: f64) -> [f64; Outcome::ALL.len()] {
+    let index = Outcome::ALL.iter().position(|&o| o == Outcome::Refait).unwrap();
+    let scale = (1.0 - refait) / (1.0 - outcomes[index]);
+    let mut settled = outcomes.map(|p| p * scale);
+    settled[index] = refait;
+    settled
+}
+
+/// Most multisets of cards on `threshold + 1` that `exact_refait` will pair
+/// up; past this the pairs take too long.
+pub const MAX_REFAIT_HANDS: usize = 20_000;
+
+/// The exact chance of a refait on a coup dealt from the top of `deck`,
+/// with the second row drawn from the cards the first one left. `None`
+/// when too many hands reach `threshold + 1` to pair them all.
+///
+/// Every card scores at least a point, so a row stops on `threshold + 1`
+/// exactly when its first cards make up one of the hands totalling that,
+/// in any order. The chance of a refait is then the sum, over every pair
+/// of such hands, of the first row drawing the one and the second row the
+/// other from what is left: two multivariate hypergeometric draws.
+pub fn exact_refait(deck: &Deck, threshold: u16) -> Option<f64> {
+    // Only the points matter, so ranks scoring alike draw as one.
+    let mut groups: BTreeMap<u16, u32> = BTreeMap::new();
+    for (&count, &value) in deck.counts().iter().zip(&deck.values()) {
+        *groups.entry(value.into()).or_default() += u32::from(count);
+    }
+    let (values, counts): (Vec<u16>, Vec<u32>) = groups.into_iter().unzip();
+    let hands = refait_hands(&values, &counts, SumThreshold(threshold).lowest_stop())?;
+
+    let total: u32 = counts.iter().sum();
+    let mut ln_factorial = vec![0.0; total as usize + 1];
+    for n in 1..=total as usize {
+        ln_factorial[n] = ln_factorial[n - 1] + (n as f64).ln();
+    }
+    let ln_choose = |n: u32, k: u32| {
+        ln_factorial[n as usize] - ln_factorial[k as usize] - ln_factorial[(n - k) as usize]
+    };
+    // The log chance that the first cards drawn from `left` are `hand`.
+    let ln_draw = |left: &[u32], hand: &[u32]| {
+        let (size, cards) = (hand.iter().sum(), left.iter().sum());
+        left.iter().zip(hand).map(|(&n, &k)| ln_choose(n, k)).sum::<f64>() - ln_choose(cards, size)
+    };
+
+    let mut refait = 0.0;
+    let mut left = vec![0; counts.len()];
+    for noir in &hands {
+        for (left, (&n, &k)) in left.iter_mut().zip(counts.iter().zip(noir)) {
+            *left = n - k;
+        }
+        let first = ln_draw(&counts, noir);
+        let second: f64 = hands
+            .iter()
+            .filter(|rouge| rouge.iter().zip(&left).all(|(k, n)| k <= n))
+            .map(|rouge| ln_draw(&left, rouge).exp())
+            .sum();
+        refait += first.exp() * second;
+    }
+    Some(refait)
+}
+
+/// Every hand, as a count per point value, that `counts` can make up
+/// totalling exactly `target`; `None` past `MAX_REFAIT_HANDS`.
+fn refait_hands(values: &[u16], counts: &[u32], target: u16) -> Option<Vec<Vec<u32>>> {
+    fn extend(
+        values: &[u16],
+        counts: &[u32],
+        target: u16,
+        hand: &mut Vec<u32>,
+        hands: &mut Vec<Vec<u32>>,
+    ) -> bool {
+        let group = hand.len();
+        if group == values.len() {
+            if target == 0 {
+                if hands.len() == MAX_REFAIT_HANDS {
+                    return false;
+                }
+                hands.push(hand.clone());
+            }
+            return true;
+        }
+        let most = counts[group].min(u32::from(target / values[group]));
+        for k in 0..=most {
+            hand.push(k);
+            let fits = extend(values, counts, target - k as u16 * values[group], hand, hands);
+            hand.pop();
+            if !fits {
+                return false;
+            }
+        }
+        true
+    }
+    let mut hands = Vec::new();
+    extend(values, counts, target, &mut Vec::new(), &mut hands).then_some(hands)
+}
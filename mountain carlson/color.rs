@@ -0,0 +1,39 @@
+use std::io::{self, IsTerminal};
+
+/// When ANSI styling is applied to console output, as chosen by `--color`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Style only when stdout is a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether console output should carry ANSI escapes.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Wraps `text` in bold when `color` is set.
+pub fn bold(text: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Wraps `text` in bold green when `color` is set.
+pub fn highlight(text: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1;32m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,199 @@
+use crate::error::{Error, Result};
+use crate::exact::{self, Solver};
+use crate::sim::{self, Counts, SimResults};
+use clap::Args;
+use log::info;
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, Deck, JokerValue, ACE_HIGH, RANKS};
+use monte_carlo_sim::dist::ExactDist;
+use monte_carlo_sim::rule::{self, SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use monte_carlo_sim::table::{Align, Table};
+use monte_carlo_sim::variant::{self, Variant};
+use std::collections::BTreeMap;
+
+/// Options of the `compare` subcommand.
+#[derive(Args)]
+pub struct CompareArgs {
+    /// Monte Carlo games to play.
+    #[arg(long, default_value_t = 1_000_000)]
+    games: u64,
+
+    /// Stop drawing once the total exceeds this value.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_THRESHOLD,
+        value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE),
+    )]
+    threshold: u8,
+
+    /// Deck to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin.
+    #[arg(long, default_value = "spanish-40", value_parser = deck::parse_arg)]
+    deck: Deck,
+
+    /// Named game to play, setting the deck, threshold and aces together:
+    /// classic-6deck, spanish-40, simplified-40 or blackjack-21. Taken by
+    /// exact, sim and compare only.
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = variant::parse_arg,
+        conflicts_with_all = ["deck", "threshold"],
+    )]
+    variant: Option<Variant>,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Count aces as soft totals busting past BUST: 11 while that keeps the
+    /// row at or under BUST, dropping back to 1 when a later card would take
+    /// it past. A blackjack dealer is `--threshold 16 --soft-aces 21`.
+    #[arg(long, value_name = "BUST", conflicts_with = "aces_high")]
+    soft_aces: Option<u16>,
+
+    /// Jokers to shuffle into the deck.
+    #[arg(long, default_value_t = 0)]
+    jokers: u16,
+
+    /// Points a joker scores: `best` for the points that take the row
+    /// nearest the threshold's stopping total, or a fixed value.
+    #[arg(long, default_value = "best", value_parser = deck::parse_joker_value)]
+    joker_value: JokerValue,
+
+    /// Draw weight of each rank, as `rank=weight` pairs such as `10=1.05`,
+    /// for a shuffle that favors some ranks; ranks not listed weigh 1.
+    #[arg(long, value_parser = deck::parse_weights)]
+    weights: Option<[f64; RANKS]>,
+
+    /// Cards already dealt from the deck, as `rank=count` pairs such as
+    /// `1=4,10=12`, to play from the shoe part way through.
+    #[arg(long, value_name = "RANK=COUNT", value_parser = deck::parse_removed)]
+    remove: Option<BTreeMap<u8, u16>>,
+
+    /// Seed for the random number generator; random when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Worker threads playing games, and solving with --solver sharded
+    /// [default: one per CPU].
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=1024))]
+    threads: Option<u64>,
+
+    /// Which solver computes the exact distributions.
+    #[arg(long, value_enum, default_value_t = Solver::Auto)]
+    solver: Solver,
+}
+
+/// One table of exact against simulated probabilities, with the largest
+/// absolute difference.
+fn compare_table(
+    label: &str,
+    exact: &BTreeMap<u16, f64>,
+    counts: &Counts,
+    games: u64,
+) -> (Table, f64) {
+    let mut values: Vec<u16> = exact.keys().copied().collect();
+    values.extend(counts.iter().map(|(v, _)| v as u16));
+    values.sort_unstable();
+    values.dedup();
+
+    let mut table = Table::new(&[
+        (label, Align::Right),
+        ("Exact", Align::Right),
+        ("Monte Carlo", Align::Right),
+        ("Difference", Align::Right),
+    ]);
+    let mut worst = 0.0f64;
+    for v in values {
+        let p_exact = exact.get(&v).copied().unwrap_or(0.0);
+        let p_mc = u8::try_from(v)
+            .ok()
+            .map_or(0.0, |v| counts.get(v) as f64 / games as f64);
+        worst = worst.max((p_mc - p_exact).abs());
+        table.add_row(vec![
+            v.to_string(),
+            format!("{:.6}%", p_exact * 100.0),
+            format!("{:.6}%", p_mc * 100.0),
+            format!("{:+.6}%", (p_mc - p_exact) * 100.0),
+        ]);
+    }
+    (table, worst)
+}
+
+/// `compare`: runs the exact solver and a Monte Carlo simulation on the same
+/// deck and lists their probabilities side by side.
+pub fn run(mut args: CompareArgs, color: bool) -> Result<()> {
+    // A variant stands in for the options it sets.
+    if let Some(variant) = args.variant.take() {
+        args.deck = variant.deck;
+        args.threshold = variant.threshold;
+        args.soft_aces = args.soft_aces.or(variant.soft_aces);
+    }
+    // Clap keeps --soft-aces from --aces-high, but not a variant's aces.
+    if args.aces_high && args.soft_aces.is_some() {
+        return Err(Error::Invalid(
+            "--aces-high conflicts with the soft aces of the variant".to_string(),
+        ));
+    }
+    if args.games == 0 {
+        return Err(Error::Invalid("--games must be at least 1".to_string()));
+    }
+    let deck = match args.values {
+        Some(values) => args.deck.with_values(values),
+        None => args.deck,
+    };
+    let deck = if args.aces_high { deck.with_ace_value(ACE_HIGH) } else { deck };
+    let soft_reach = args
+        .soft_aces
+        .map(|bust| rule::soft_reach(bust, args.threshold.into()))
+        .transpose()
+        .map_err(|e| Error::Invalid(format!("--soft-aces: {}", e)))?;
+    let deck = deck
+        .with_jokers(args.jokers, args.joker_value)
+        .with_soft_aces(soft_reach)
+        .with_weights(args.weights.unwrap_or(deck::FAIR_WEIGHTS));
+    let deck = match &args.remove {
+        Some(removed) => {
+            deck.without(removed).map_err(|e| Error::Invalid(format!("--remove: {}", e)))?
+        }
+        None => deck,
+    };
+    let rule = SumThreshold(args.threshold.into());
+    sim::check_game_width(&deck, &rule)?;
+    let threads = args.threads.unwrap_or_else(sim::default_threads);
+    let dist = exact::solve(&deck, &rule, args.solver, threads)?;
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        info!(
+            "Using random seed {} (pass --seed {} --threads {} to reproduce this run).",
+            seed, seed, threads
+        );
+    }
+    let results = sim::simulate(&deck, &rule, args.games, seed, threads);
+
+    print_comparison(&dist, &results, color);
+    Ok(())
+}
+
+/// Prints the exact and simulated score and length tables side by side.
+pub fn print_comparison(dist: &ExactDist, results: &SimResults, color: bool) {
+    println!("Exact solution vs {} Monte Carlo games\n", results.total_games);
+    let sections = [
+        ("Score", &dist.scores, &results.score_counts),
+        ("Length", &dist.lengths, &results.length_counts),
+    ];
+    for (label, exact, counts) in sections {
+        let (table, worst) = compare_table(label, exact, counts, results.total_games);
+        println!("{}", color::bold(&format!("--- {} Distribution ---", label), color));
+        print!("{}", table.render_styled(color));
+        println!("Largest difference: {:.6} points\n", worst * 100.0);
+    }
+}
@@ -0,0 +1,502 @@
+use crate::coup;
+use crate::error::{Error, Result};
+use crate::exact::{self, Solver};
+use crate::sim;
+use clap::Args;
+use log::info;
+use monte_carlo_sim::bet::{self, Bet, Payouts, RefaitRule, Settlement};
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, Deck, ACE_HIGH, RANKS};
+use monte_carlo_sim::rule::{SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use monte_carlo_sim::shoe::{self, Coup, Shoe, DEFAULT_PENETRATION};
+use monte_carlo_sim::table::{argmax, Align, Table};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Options of the `counting` subcommand.
+#[derive(Args)]
+pub struct CountingArgs {
+    /// Number of shoes to deal. Every coup costs an exact solve of the
+    /// cards left, so a six-deck shoe takes seconds.
+    #[arg(long, default_value_t = 10)]
+    shoes: u64,
+
+    /// Units the counter stakes on the best bet when the cards left favor
+    /// the player, against one unit otherwise.
+    #[arg(long, default_value_t = 8.0)]
+    spread: f64,
+
+    /// Rows of the penetration table, each covering an equal share of the
+    /// shoe dealt before the cut.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..=100))]
+    buckets: u64,
+
+    /// How a refait settles the bets: half, prison, or commission with an
+    /// optional percentage such as `commission=2.5`.
+    #[arg(long, value_name = "RULE", default_value = "half", value_parser = bet::parse_refait)]
+    refait: RefaitRule,
+
+    /// What each bet pays on a win, as `bet=multiple` pairs such as
+    /// `couleur=0.95`; bets not listed pay even money.
+    #[arg(long, value_parser = bet::parse_payouts, default_value = "", hide_default_value = true)]
+    payouts: Payouts,
+
+    /// Stop drawing each row once its total exceeds this value.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_THRESHOLD,
+        value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE),
+    )]
+    threshold: u8,
+
+    /// Shoe to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin.
+    #[arg(long, default_value = "six-deck", value_parser = deck::parse_arg)]
+    deck: Deck,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Cards already dealt from the shoe, as `rank=count` pairs such as
+    /// `1=24`; every shoe is then dealt from the cards left, the count
+    /// starting where those cards left it.
+    #[arg(long, value_name = "RANK=COUNT", value_parser = deck::parse_removed)]
+    remove: Option<BTreeMap<u8, u16>>,
+
+    /// Share of the shoe dealt before the cut card calls a reshuffle, and so
+    /// the deepest the count gets.
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = DEFAULT_PENETRATION,
+        value_parser = shoe::parse_penetration,
+    )]
+    penetration: f64,
+
+    /// Seed for the random number generator; random when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Worker threads, each dealing whole shoes [default: one per CPU].
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=1024))]
+    threads: Option<u64>,
+
+    /// Also save the report to this file.
+    #[arg(long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// The rules a coup is dealt and settled by.
+struct Rules {
+    threshold: u8,
+    penetration: f64,
+    refait: RefaitRule,
+    payouts: Payouts,
+}
+
+/// The bet a flat bettor makes on every coup, for comparison.
+const FLAT_BET: Bet = Bet::Noir;
+
+/// One coup dealt, with the edge of the best bet on it as the player could
+/// work it out from the cards left, and how the counter and a flat bettor
+/// fared on it.
+struct CoupEdge {
+    /// Cards dealt from the shoe before the coup.
+    dealt: usize,
+    /// The player's expected gain per unit on the best bet.
+    edge: f64,
+    /// What the counter, then the flat bettor, staked and made.
+    wagers: [Wager; 2],
+}
+
+/// A player's stake on one coup, with the result and the expected result.
+#[derive(Clone, Copy, Default)]
+struct Wager {
+    staked: f64,
+    net: f64,
+    expected: f64,
+}
+
+/// The player's expected gain per unit on every bet of a coup dealt from
+/// `residual`, indexed like `Bet::ALL`. Each row is solved exactly on the
+/// cards left, and the rows are taken as independent, as in `coup`.
+fn edges(residual: &Deck, rules: &Rules) -> Result<[f64; Bet::ALL.len()]> {
+    let row = exact::solve(residual, &SumThreshold(rules.threshold.into()), Solver::Auto, 1)?;
+    let probs = bet::independent_rows(&row.scores, rules.threshold.into());
+    Ok(Bet::ALL.map(|bet| bet.odds(&probs, &rules.payouts).expected_return(rules.refait)))
+}
+
+/// A player betting coup after coup. A stake imprisoned by a refait waits
+/// for the next decided coup, and no new bet is made meanwhile.
+#[derive(Default)]
+struct Player {
+    prison: Option<(Bet, f64)>,
+}
+
+impl Player {
+    /// Plays `coup` for a player who would stake `stake` on `bet`, worth
+    /// `edge` per unit. An imprisoned stake counts as lost when it is
+    /// forfeited, and as nothing when freed.
+    fn play(&mut self, bet: Bet, stake: f64, edge: f64, coup: &Coup, rules: &Rules) -> Wager {
+        let outcome = coup.outcome(rules.threshold);
+        if let Some((bet, stake)) = self.prison {
+            let settlement = bet.settle(outcome, coup.first_card_black);
+            if settlement != Settlement::Push {
+                self.prison = None;
+            }
+            let net = match settlement {
+                Settlement::Lose | Settlement::Refait => -stake,
+                Settlement::Win | Settlement::Push => 0.0,
+            };
+            return Wager { net, ..Wager::default() };
+        }
+        let net = match bet.settle(outcome, coup.first_card_black) {
+            Settlement::Win => stake * rules.payouts.get(bet),
+            Settlement::Lose => -stake,
+            Settlement::Push => 0.0,
+            Settlement::Refait => match rules.refait {
+                RefaitRule::Half => -stake / 2.0,
+                RefaitRule::Commission(rate) => -stake * rate,
+                RefaitRule::Prison => {
+                    self.prison = Some((bet, stake));
+                    0.0
+                }
+            },
+        };
+        Wager {
+            staked: stake,
+            net,
+            expected: stake * edge,
+        }
+    }
+}
+
+/// Deals one shoe shuffled from `seed` to its cut, with the edge of every
+/// coup and what the counter and the flat bettor made on it. A coup the
+/// shoe runs out on is void and left out, and a stake still imprisoned at
+/// the end is returned.
+fn play_shoe(deck: &Deck, rules: &Rules, spread: f64, seed: u64) -> Result<Vec<CoupEdge>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut shoe = Shoe::new(deck).with_penetration(rules.penetration);
+    shoe.shuffle(&mut rng);
+    let (mut counter, mut flat) = (Player::default(), Player::default());
+    let mut coups = Vec::new();
+    while !shoe.at_cut() {
+        let dealt = deck.len() - shoe.remaining();
+        let edges = edges(&shoe.residual(deck), rules)?;
+        let Some(coup) = shoe.deal_coup(rules.threshold) else {
+            break;
+        };
+        let best = argmax(edges.iter()).expect("there are bets");
+        let edge = edges[best];
+        let stake = counter_stake(edge, spread);
+        coups.push(CoupEdge {
+            dealt,
+            edge,
+            wagers: [
+                counter.play(Bet::ALL[best], stake, edge, &coup, rules),
+                flat.play(FLAT_BET, 1.0, edges[FLAT_BET as usize], &coup, rules),
+            ],
+        });
+    }
+    Ok(coups)
+}
+
+/// Deals one shoe per seed on `threads` workers. The shoes come back in
+/// seed order, so the report does not depend on the thread count.
+fn play_shoes(
+    deck: &Deck,
+    rules: &Rules,
+    spread: f64,
+    seeds: &[u64],
+    threads: u64,
+) -> Result<Vec<Vec<CoupEdge>>> {
+    let next = AtomicUsize::new(0);
+    let shoes: Mutex<Vec<Option<Result<Vec<CoupEdge>>>>> =
+        Mutex::new(seeds.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..threads.min(seeds.len() as u64) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&seed) = seeds.get(index) else {
+                    break;
+                };
+                let shoe = play_shoe(deck, rules, spread, seed);
+                shoes.lock().unwrap()[index] = Some(shoe);
+            });
+        }
+    });
+    shoes
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|shoe| shoe.expect("every shoe was dealt"))
+        .collect()
+}
+
+/// The units the counter stakes on a coup with the given edge.
+fn counter_stake(edge: f64, spread: f64) -> f64 {
+    if edge > 0.0 {
+        spread
+    } else {
+        1.0
+    }
+}
+
+/// Formats the edge by penetration, where it turns positive and where the
+/// counter breaks even. The rows share out the `penetration` of the shoe
+/// dealt, but are labelled by how deep into the whole shoe they reach.
+fn format_report(
+    shoes: &[Vec<CoupEdge>],
+    deck_len: usize,
+    penetration: f64,
+    buckets: usize,
+    spread: f64,
+    refait: RefaitRule,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Counting Advantage ---", color));
+    out.push('\n');
+    let coups: Vec<&CoupEdge> = shoes.iter().flatten().collect();
+    let positive = coups.iter().filter(|coup| coup.edge > 0.0).count();
+    out.push_str(&format!(
+        "Shoes: {}, coups: {}, refait {}, spread 1-{}, cut at {}%\n",
+        shoes.len(),
+        coups.len(),
+        refait,
+        spread,
+        penetration * 100.0
+    ));
+    out.push_str(&format!(
+        "Coups with a player edge: {} ({:.4}%)\n",
+        positive,
+        positive as f64 / coups.len() as f64 * 100.0
+    ));
+    if let Some(best) = coups.iter().map(|coup| coup.edge).reduce(f64::max) {
+        out.push_str(&format!("Best edge seen: {:+.4}%\n", best * 100.0));
+    }
+
+    // Each row covers the coups started within its share of the shoe; the
+    // counter's edge is over every coup up to the end of the row, as if
+    // the shoe were reshuffled there.
+    let mut table = Table::new(&[
+        ("Penetration", Align::Left),
+        ("Coups", Align::Right),
+        ("Best-bet edge", Align::Right),
+        ("Edge > 0", Align::Right),
+        ("Counter edge", Align::Right),
+    ]);
+    let (mut staked, mut gained) = (0.0, 0.0);
+    let mut breakeven = None;
+    let mut turns_positive = None;
+    let dealt_len = (deck_len as f64 * penetration).ceil() as usize;
+    let percent = |cards: usize| cards * 100 / deck_len;
+    for bucket in 0..buckets {
+        let start = dealt_len * bucket / buckets;
+        let end = dealt_len * (bucket + 1) / buckets;
+        let in_bucket: Vec<&&CoupEdge> = coups
+            .iter()
+            .filter(|coup| (start..end).contains(&coup.dealt))
+            .collect();
+        for coup in &in_bucket {
+            let stake = counter_stake(coup.edge, spread);
+            staked += stake;
+            gained += stake * coup.edge;
+        }
+        let share = |count: usize| {
+            if in_bucket.is_empty() {
+                "-".to_string()
+            } else {
+                format!("{:.2}%", count as f64 / in_bucket.len() as f64 * 100.0)
+            }
+        };
+        let mean_edge = if in_bucket.is_empty() {
+            "-".to_string()
+        } else {
+            let n = in_bucket.len() as f64;
+            let mean = in_bucket.iter().map(|coup| coup.edge).sum::<f64>() / n;
+            if mean > 0.0 && turns_positive.is_none() {
+                turns_positive = Some(percent(start));
+            }
+            if in_bucket.len() > 1 {
+                let var = in_bucket.iter().map(|coup| (coup.edge - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                format!("{:+.4}% ± {:.4}%", mean * 100.0, (var / n).sqrt() * 100.0)
+            } else {
+                format!("{:+.4}%", mean * 100.0)
+            }
+        };
+        let counter_edge = if staked > 0.0 {
+            let edge = gained / staked;
+            if edge >= 0.0 && breakeven.is_none() {
+                breakeven = Some(percent(end));
+            }
+            format!("{:+.4}%", edge * 100.0)
+        } else {
+            "-".to_string()
+        };
+        table.add_row(vec![
+            format!("{}-{}%", percent(start), percent(end)),
+            in_bucket.len().to_string(),
+            mean_edge,
+            share(in_bucket.iter().filter(|coup| coup.edge > 0.0).count()),
+            counter_edge,
+        ]);
+    }
+    out.push_str(&table.render());
+    match turns_positive {
+        Some(depth) => out.push_str(&format!(
+            "Best-bet edge turns positive: from {}% of the shoe\n",
+            depth
+        )),
+        None => out.push_str("Best-bet edge turns positive: never\n"),
+    }
+    match breakeven {
+        Some(depth) => out.push_str(&format!(
+            "Breakeven penetration: {}% of the shoe\n",
+            depth
+        )),
+        None => out.push_str("Breakeven penetration: none, the counter's edge stays negative\n"),
+    }
+    out
+}
+
+/// Formats what the counter and the flat bettor actually made over the
+/// shoes dealt, next to what their edges predicted. The standard error
+/// comes from the spread of the results between shoes.
+fn format_comparison(shoes: &[Vec<CoupEdge>], spread: f64, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Counter against Flat Betting ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Player", Align::Left),
+        ("Units staked", Align::Right),
+        ("Net result", Align::Right),
+        ("Per unit", Align::Right),
+        ("Expected per unit", Align::Right),
+    ]);
+    let players = [
+        format!("Counter (1-{} on the best bet)", spread),
+        format!("Flat (1 on {})", FLAT_BET.name()),
+    ];
+    let mut nets = Vec::new();
+    for (player, name) in players.iter().enumerate() {
+        let per_shoe: Vec<Wager> = shoes
+            .iter()
+            .map(|shoe| {
+                shoe.iter().map(|coup| coup.wagers[player]).fold(Wager::default(), |total, coup| Wager {
+                    staked: total.staked + coup.staked,
+                    net: total.net + coup.net,
+                    expected: total.expected + coup.expected,
+                })
+            })
+            .collect();
+        let staked: f64 = per_shoe.iter().map(|shoe| shoe.staked).sum();
+        let net: f64 = per_shoe.iter().map(|shoe| shoe.net).sum();
+        let expected: f64 = per_shoe.iter().map(|shoe| shoe.expected).sum();
+        let n = per_shoe.len() as f64;
+        let se = if per_shoe.len() > 1 {
+            let mean = net / n;
+            let var = per_shoe.iter().map(|shoe| (shoe.net - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            format!(" ± {:.2}", (var * n).sqrt())
+        } else {
+            String::new()
+        };
+        let per_unit = |total: f64| {
+            if staked > 0.0 {
+                format!("{:+.4}%", total / staked * 100.0)
+            } else {
+                "-".to_string()
+            }
+        };
+        table.add_row(vec![
+            name.clone(),
+            format!("{:.0}", staked),
+            format!("{:+.2}{}", net, se),
+            per_unit(net),
+            per_unit(expected),
+        ]);
+        nets.push(net);
+    }
+    out.push_str(&table.render());
+    out.push_str(&format!(
+        "Counter's net result against flat betting: {:+.2} units\n",
+        nets[0] - nets[1]
+    ));
+    out
+}
+
+/// `counting`: deals shoes to the end and works out, before every coup,
+/// the player's edge from the cards left, then reports where a counter
+/// varying the stake on that edge breaks even and what it made against
+/// flat betting.
+pub fn run(args: CountingArgs, color: bool) -> Result<()> {
+    let deck = match args.values {
+        Some(values) => args.deck.with_values(values),
+        None => args.deck,
+    };
+    let deck = if args.aces_high { deck.with_ace_value(ACE_HIGH) } else { deck };
+    let deck = match &args.remove {
+        Some(removed) => {
+            deck.without(removed).map_err(|e| Error::Invalid(format!("--remove: {}", e)))?
+        }
+        None => deck,
+    };
+    if args.shoes == 0 {
+        return Err(Error::Invalid("--shoes must be at least 1".to_string()));
+    }
+    if !(args.spread.is_finite() && args.spread >= 1.0) {
+        return Err(Error::Invalid("--spread must be at least 1".to_string()));
+    }
+    coup::check_coup_deck(&deck, args.threshold)?;
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        info!("Using random seed {} (pass --seed {} to reproduce this run).", seed, seed);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let seeds: Vec<u64> = (0..args.shoes).map(|_| rng.gen()).collect();
+    let threads = args.threads.unwrap_or_else(sim::default_threads);
+    let rules = Rules {
+        threshold: args.threshold,
+        penetration: args.penetration,
+        refait: args.refait,
+        payouts: args.payouts,
+    };
+    let shoes = play_shoes(&deck, &rules, args.spread, &seeds, threads)?;
+
+    let report = |color| {
+        format!(
+            "{}\n{}",
+            format_report(
+                &shoes,
+                deck.len(),
+                args.penetration,
+                args.buckets as usize,
+                args.spread,
+                args.refait,
+                color,
+            ),
+            format_comparison(&shoes, args.spread, color)
+        )
+    };
+    print!("{}", report(color));
+    if let Some(path) = &args.output {
+        fs::write(path, report(false))
+            .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+        info!("Report saved to '{}'", path);
+    }
+    Ok(())
+}
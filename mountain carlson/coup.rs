@@ -0,0 +1,899 @@
+use crate::error::{Error, Result};
+use crate::exact::{self, Solver};
+use crate::sim;
+use clap::Args;
+use log::info;
+use monte_carlo_sim::bet::{self, Bet, BetOdds, Payouts, RefaitRule, Settlement};
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, rank, rank_index, Deck, ACE_HIGH, RANKS};
+use monte_carlo_sim::rule::{SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use monte_carlo_sim::shoe::{self, Burn, Outcome, Shoe, DEFAULT_PENETRATION};
+use monte_carlo_sim::table::{argmax, Align, Table};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Options of the `coup` subcommand.
+#[derive(Args)]
+pub struct CoupArgs {
+    /// Number of coups to deal.
+    #[arg(long, default_value_t = 1_000_000)]
+    games: u64,
+
+    /// Stop drawing each row once its total exceeds this value.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_THRESHOLD,
+        value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE),
+    )]
+    threshold: u8,
+
+    /// Stop drawing the Rouge row once its total exceeds this value instead,
+    /// as in variants where the second row draws to a total of its own. A
+    /// refait stays a tie on the Noir row's lowest stop [default: --threshold].
+    #[arg(long, value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE))]
+    rouge_threshold: Option<u8>,
+
+    /// Shoe to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin.
+    #[arg(long, default_value = "six-deck", value_parser = deck::parse_arg)]
+    deck: Deck,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Take cards out of the shoe before it is dealt, as `rank=count` pairs
+    /// such as `1=24` for a six-deck shoe without its aces. The report then
+    /// shows how the cards gone move the house edge.
+    #[arg(long, value_name = "RANK=COUNT", value_parser = deck::parse_removed)]
+    remove: Option<BTreeMap<u8, u16>>,
+
+    /// Burn a card off the top of every shoe before its first coup: random,
+    /// or the rank of the card to burn. The report then shows how the burn
+    /// moves the house edge.
+    #[arg(long, value_name = "CARD", value_parser = shoe::parse_burn)]
+    burn: Option<Burn>,
+
+    /// Share of the shoe dealt before the cut card calls a reshuffle; the
+    /// coup under way when it comes out is dealt to its end.
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = DEFAULT_PENETRATION,
+        value_parser = shoe::parse_penetration,
+    )]
+    penetration: f64,
+
+    /// Seed for the random number generator; random when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How a refait settles the even-money bets in the house edge report:
+    /// half, prison, or commission with an optional percentage such as
+    /// `commission=2.5` [default: all three, at a 5% commission].
+    #[arg(long, value_name = "RULE", value_parser = bet::parse_refait)]
+    refait: Option<RefaitRule>,
+
+    /// What each bet pays on a win, as `bet=multiple` pairs such as
+    /// `couleur=0.95`; bets not listed pay even money.
+    #[arg(long, value_parser = bet::parse_payouts, default_value = "", hide_default_value = true)]
+    payouts: Payouts,
+
+    /// Premium for insurance against a refait, a percentage of the stake
+    /// paid on every coup insured, as the house quotes it. Without it the
+    /// report gives the premium at which insurance breaks even.
+    #[arg(long, value_name = "PCT")]
+    insurance: Option<f64>,
+
+    /// Also report the expected result and its spread over a session of
+    /// this many coups.
+    #[arg(long, value_name = "COUPS")]
+    session: Option<u64>,
+
+    /// Stake on every coup of a --session.
+    #[arg(long, default_value_t = 1.0, requires = "session")]
+    stake: f64,
+
+    /// Also save the report to this file.
+    #[arg(long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// How often each outcome came up over a number of coups.
+pub struct CoupResults {
+    /// Counts indexed like `Outcome::ALL`.
+    pub counts: [u64; Outcome::ALL.len()],
+    pub coups: u64,
+    /// Shoes started, including the first.
+    pub shoes: u64,
+    /// Coups won by the couleur and the inverse bets.
+    pub couleur_wins: u64,
+    pub inverse_wins: u64,
+    /// How many shoes dealt to the cut held each number of refaits. The
+    /// shoe the run stops in is left out.
+    pub shoe_refaits: BTreeMap<u64, u64>,
+    /// Coups dealt from the shoes in `shoe_refaits`.
+    pub shoe_coups: u64,
+}
+
+/// The totals the two rows of a coup draw past. The Noir row's also sets
+/// the refait total.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Thresholds {
+    pub noir: u8,
+    pub rouge: u8,
+}
+
+/// Deals `coups` coups from `deck`, reshuffling once `penetration` of the
+/// shoe is dealt or it runs out, and burning a card after each shuffle if
+/// `burn` says so.
+pub fn play_coups(
+    deck: &Deck,
+    thresholds: Thresholds,
+    burn: Option<Burn>,
+    penetration: f64,
+    coups: u64,
+    rng: &mut StdRng,
+) -> CoupResults {
+    let mut shoe = Shoe::new(deck).with_penetration(penetration);
+    if let Some(burn) = burn {
+        shoe = shoe.with_burn(burn, deck);
+    }
+    shoe.shuffle(rng);
+    let mut results = CoupResults {
+        counts: [0; Outcome::ALL.len()],
+        coups: 0,
+        shoes: 1,
+        couleur_wins: 0,
+        inverse_wins: 0,
+        shoe_refaits: BTreeMap::new(),
+        shoe_coups: 0,
+    };
+    let noir = SumThreshold(thresholds.noir.into());
+    let rouge = SumThreshold(thresholds.rouge.into());
+    // Coups and refaits of the shoe being dealt.
+    let (mut shoe_coups, mut shoe_refaits) = (0, 0);
+    while results.coups < coups {
+        match shoe.deal_coup_by(&noir, &rouge) {
+            Some(coup) => {
+                let outcome = coup.outcome(thresholds.noir);
+                let index = Outcome::ALL.iter().position(|&o| o == outcome).unwrap();
+                results.counts[index] += 1;
+                results.coups += 1;
+                let won = |bet: Bet| bet.settle(outcome, coup.first_card_black) == Settlement::Win;
+                results.couleur_wins += won(Bet::Couleur) as u64;
+                results.inverse_wins += won(Bet::Inverse) as u64;
+                shoe_coups += 1;
+                shoe_refaits += (outcome == Outcome::Refait) as u64;
+            }
+            None => {
+                *results.shoe_refaits.entry(shoe_refaits).or_insert(0) += 1;
+                results.shoe_coups += shoe_coups;
+                (shoe_coups, shoe_refaits) = (0, 0);
+                shoe.shuffle(rng);
+                results.shoes += 1;
+            }
+        }
+    }
+    results
+}
+
+impl CoupResults {
+    /// The share of coups that ended in each outcome, indexed like `Outcome::ALL`.
+    pub fn probabilities(&self) -> [f64; Outcome::ALL.len()] {
+        self.counts.map(|count| count as f64 / self.coups as f64)
+    }
+
+    /// The odds of the couleur or inverse bet as dealt.
+    fn color_odds(&self, bet: Bet, payouts: &Payouts) -> BetOdds {
+        let [noir, rouge, tie, refait] = self.probabilities();
+        let wins = match bet {
+            Bet::Couleur => self.couleur_wins,
+            _ => self.inverse_wins,
+        };
+        let win = wins as f64 / self.coups as f64;
+        BetOdds {
+            win,
+            lose: noir + rouge - win,
+            tie,
+            refait,
+            payout: payouts.get(bet),
+        }
+    }
+}
+
+/// The decks a burn may leave to deal from, each with its chance: every
+/// rank in proportion to its cards for a random burn, else the one deck
+/// without the burned card.
+fn burned_decks(deck: &Deck, burn: Burn) -> Vec<(Deck, f64)> {
+    let counts = deck.counts();
+    let without = |i: usize| {
+        let mut left = deck.clone();
+        left.remove(rank(i), 1).expect("only ranks holding cards are burned");
+        left
+    };
+    match burn {
+        Burn::Rank(rank) => vec![(without(rank_index(rank)), 1.0)],
+        Burn::Random => (0..RANKS)
+            .filter(|&i| counts[i] > 0)
+            .map(|i| (without(i), counts[i] as f64 / deck.len() as f64))
+            .collect(),
+    }
+}
+
+/// The exact chances of a coup off the top of a shoe.
+#[derive(Clone, Copy)]
+struct ExactCoup {
+    /// Outcome chances, indexed like `Outcome::ALL`, with the refait chance
+    /// taken from dependent rows where there is one (see `bet::with_refait`).
+    outcomes: [f64; Outcome::ALL.len()],
+    /// The refait chance with independent rows.
+    independent_refait: f64,
+    /// The refait chance with the second row drawn from what the first left.
+    dependent: Option<f64>,
+}
+
+/// The exact chances of a coup off the top of `deck`. The rows are solved
+/// independently and the refait chance then replaced by the one with
+/// dependent rows where there is one. Rows drawing to different totals
+/// have none: a Rouge row on the refait total then depends on the order
+/// of its cards.
+fn exact_outcomes(deck: &Deck, thresholds: Thresholds) -> Result<ExactCoup> {
+    let solve = |threshold: u8| {
+        exact::solve(deck, &SumThreshold(threshold.into()), Solver::Auto, sim::default_threads())
+    };
+    let refait = Outcome::ALL.iter().position(|&o| o == Outcome::Refait).unwrap();
+    let noir = solve(thresholds.noir)?;
+    if thresholds.rouge != thresholds.noir {
+        let rouge = solve(thresholds.rouge)?;
+        let exact = bet::independent_rows_apart(&noir.scores, &rouge.scores, thresholds.noir.into());
+        return Ok(ExactCoup { outcomes: exact, independent_refait: exact[refait], dependent: None });
+    }
+    let independent = bet::independent_rows(&noir.scores, thresholds.noir.into());
+    let dependent = bet::exact_refait(deck, thresholds.noir.into());
+    Ok(ExactCoup {
+        outcomes: dependent.map_or(independent, |p| bet::with_refait(independent, p)),
+        independent_refait: independent[refait],
+        dependent,
+    })
+}
+
+/// `exact_outcomes` after `burn`, averaged over the card it may take.
+fn burned_outcomes(deck: &Deck, thresholds: Thresholds, burn: Burn) -> Result<ExactCoup> {
+    let mut burned = ExactCoup {
+        outcomes: [0.0; Outcome::ALL.len()],
+        independent_refait: 0.0,
+        dependent: Some(0.0),
+    };
+    for (left, p) in burned_decks(deck, burn) {
+        let exact = exact_outcomes(&left, thresholds)?;
+        for (sum, q) in burned.outcomes.iter_mut().zip(exact.outcomes) {
+            *sum += p * q;
+        }
+        burned.independent_refait += p * exact.independent_refait;
+        burned.dependent = burned.dependent.zip(exact.dependent).map(|(sum, q)| sum + p * q);
+    }
+    Ok(burned)
+}
+
+/// How burning a card moves the exact house edge on Noir under each of
+/// `rules`, against the standard error of the edge `results` measured. A
+/// move under two standard errors is lost in the noise of this many coups.
+fn format_burn(
+    burn: Burn,
+    unburned: &[f64; Outcome::ALL.len()],
+    burned: &[f64; Outcome::ALL.len()],
+    results: &CoupResults,
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Burn Card ---", color));
+    out.push('\n');
+    match burn {
+        Burn::Random => out.push_str("Burned: the top card of each shoe, unseen\n"),
+        Burn::Rank(rank) => out.push_str(&format!("Burned: a {} from each shoe\n", rank)),
+    }
+    let mut table = Table::new(&[
+        ("Refait", Align::Left),
+        ("No burn", Align::Right),
+        ("Burn", Align::Right),
+        ("Difference", Align::Right),
+        ("Std error", Align::Right),
+        ("Measurable", Align::Left),
+    ]);
+    for &rule in rules {
+        let before = Bet::Noir.odds(unburned, payouts).house_edge(rule);
+        let odds = Bet::Noir.odds(burned, payouts);
+        let after = odds.house_edge(rule);
+        let se = (odds.variance(rule) / results.coups as f64).sqrt();
+        let measurable = (after - before).abs() > 2.0 * se;
+        table.add_row(vec![
+            rule.to_string(),
+            format!("{:.4}%", before * 100.0),
+            format!("{:.4}%", after * 100.0),
+            format!("{:+.6}%", (after - before) * 100.0),
+            format!("{:.4}%", se * 100.0),
+            if measurable { "yes" } else { "no" }.to_string(),
+        ]);
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// How taking the `removed` cards out of the shoe moves the exact house
+/// edge on Noir under each of `rules`, from the `full` shoe to the cards
+/// `left`.
+fn format_removed(
+    removed: &BTreeMap<u8, u16>,
+    full: &[f64; Outcome::ALL.len()],
+    left: &[f64; Outcome::ALL.len()],
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Cards Removed ---", color));
+    out.push('\n');
+    let removed: Vec<String> = removed
+        .iter()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(rank, count)| format!("{} of rank {}", count, rank))
+        .collect();
+    if removed.is_empty() {
+        out.push_str("Removed: none\n");
+    } else {
+        out.push_str(&format!("Removed: {}\n", removed.join(", ")));
+    }
+    let mut table = Table::new(&[
+        ("Refait", Align::Left),
+        ("Full shoe", Align::Right),
+        ("Cards left", Align::Right),
+        ("Difference", Align::Right),
+    ]);
+    for &rule in rules {
+        let before = Bet::Noir.odds(full, payouts).house_edge(rule);
+        let after = Bet::Noir.odds(left, payouts).house_edge(rule);
+        table.add_row(vec![
+            rule.to_string(),
+            format!("{:.4}%", before * 100.0),
+            format!("{:.4}%", after * 100.0),
+            format!("{:+.6}%", (after - before) * 100.0),
+        ]);
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// How far apart the Noir and Rouge bets come out when the rows draw to
+/// different totals, exact with independent rows.
+fn format_asymmetry(
+    thresholds: Thresholds,
+    exact: &[f64; Outcome::ALL.len()],
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Noir against Rouge ---", color));
+    out.push('\n');
+    out.push_str(&format!(
+        "Noir draws past {}, Rouge past {}: Noir wins {:.4}%, Rouge {:.4}%\n",
+        thresholds.noir,
+        thresholds.rouge,
+        exact[0] * 100.0,
+        exact[1] * 100.0
+    ));
+    let mut table = Table::new(&[
+        ("Refait", Align::Left),
+        ("Noir edge", Align::Right),
+        ("Rouge edge", Align::Right),
+        ("Rouge - Noir", Align::Right),
+    ]);
+    for &rule in rules {
+        let noir = Bet::Noir.odds(exact, payouts).house_edge(rule);
+        let rouge = Bet::Rouge.odds(exact, payouts).house_edge(rule);
+        table.add_row(vec![
+            rule.to_string(),
+            format!("{:.4}%", noir * 100.0),
+            format!("{:.4}%", rouge * 100.0),
+            format!("{:+.4}%", (rouge - noir) * 100.0),
+        ]);
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// The house edge on the even-money bets under each of `rules`: exact, with
+/// the refait chance of dependent rows where there is one, how far that is
+/// from the half-lost rule, and as measured on each side by the simulation.
+fn format_house_edge(
+    exact: &[f64; Outcome::ALL.len()],
+    results: &CoupResults,
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- House Edge (Noir and Rouge) ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Refait", Align::Left),
+        ("Exact", Align::Right),
+        ("vs half lost", Align::Right),
+        ("Noir", Align::Right),
+        ("Rouge", Align::Right),
+    ]);
+    let simulated = results.probabilities();
+    let half = Bet::Noir.odds(exact, payouts).house_edge(RefaitRule::Half);
+    for &rule in rules {
+        let edge = |probs, bet: Bet| {
+            format!("{:.4}%", bet.odds(probs, payouts).house_edge(rule) * 100.0)
+        };
+        let difference = Bet::Noir.odds(exact, payouts).house_edge(rule) - half;
+        table.add_row(vec![
+            rule.to_string(),
+            edge(exact, Bet::Noir),
+            format!("{:+.4}%", difference * 100.0),
+            edge(&simulated, Bet::Noir),
+            edge(&simulated, Bet::Rouge),
+        ]);
+    }
+    out.push_str(&table.render());
+    let decided: Vec<String> = rules
+        .iter()
+        .map(|&rule| {
+            let edge = Bet::Noir.odds(exact, payouts).house_edge_decided(rule);
+            format!("{} {:.4}%", rule, edge * 100.0)
+        })
+        .collect();
+    out.push_str(&format!("Exact per decided coup, ties excluded: {}\n", decided.join(", ")));
+    out
+}
+
+/// The chance of a refait: exact with the second row drawn from what the
+/// first left, as the independent rows put it, and as dealt. The coups
+/// dealt run to the cut of each shoe, so they stray a little from the
+/// exact chance off the top.
+fn format_refait(exact: &ExactCoup, results: &CoupResults, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Refait Chance ---", color));
+    out.push('\n');
+    let refait = Outcome::ALL.iter().position(|&o| o == Outcome::Refait).unwrap();
+    let n = results.coups as f64;
+    let simulated = results.counts[refait] as f64 / n;
+    let se = (simulated * (1.0 - simulated) / n).sqrt();
+    let independent = exact.independent_refait;
+    match exact.dependent {
+        Some(p) => {
+            out.push_str(&format!("Exact P(refait): {:.12}%\n", p * 100.0));
+            out.push_str(&format!(
+                "Independent rows: {:.12}% ({:+.6}%)\n",
+                independent * 100.0,
+                (independent - p) * 100.0
+            ));
+            out.push_str(&format!(
+                "Simulated: {:.6}% ± {:.6}% ({:+.2} standard errors from exact)\n",
+                simulated * 100.0,
+                se * 100.0,
+                if se > 0.0 { (simulated - p) / se } else { 0.0 }
+            ));
+        }
+        None => {
+            out.push_str(&format!(
+                "Exact P(refait): not computed, more than {} hands reach the threshold\n",
+                bet::MAX_REFAIT_HANDS
+            ));
+            out.push_str(&format!("Independent rows: {:.12}%\n", independent * 100.0));
+            out.push_str(&format!("Simulated: {:.6}% ± {:.6}%\n", simulated * 100.0, se * 100.0));
+        }
+    }
+    out
+}
+
+/// Refaits per shoe dealt to its cut: their mean and distribution, and the
+/// mean that the exact refait chance predicts for as many coups a shoe.
+fn format_shoe_refaits(exact: &[f64; Outcome::ALL.len()], results: &CoupResults, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Refaits per Shoe ---", color));
+    out.push('\n');
+    let shoes: u64 = results.shoe_refaits.values().sum();
+    if shoes == 0 {
+        out.push_str("No shoe was dealt to its cut; deal more coups.\n");
+        return out;
+    }
+    let n = shoes as f64;
+    let refaits: u64 = results.shoe_refaits.iter().map(|(&r, &count)| r * count).sum();
+    let mean = refaits as f64 / n;
+    let variance = results
+        .shoe_refaits
+        .iter()
+        .map(|(&r, &count)| (r as f64 - mean).powi(2) * count as f64)
+        .sum::<f64>()
+        / n;
+    let coups_per_shoe = results.shoe_coups as f64 / n;
+    let refait = Outcome::ALL.iter().position(|&o| o == Outcome::Refait).unwrap();
+    out.push_str(&format!(
+        "Complete shoes: {}, {:.2} coups a shoe\n",
+        shoes, coups_per_shoe
+    ));
+    out.push_str(&format!(
+        "Refaits a shoe: {:.4} ± {:.4} (exact chance × coups a shoe: {:.4})\n",
+        mean,
+        (variance / n).sqrt(),
+        exact[refait] * coups_per_shoe
+    ));
+    let mut table = Table::new(&[
+        ("Refaits", Align::Right),
+        ("Shoes", Align::Right),
+        ("Share", Align::Right),
+    ]);
+    for (&r, &count) in &results.shoe_refaits {
+        table.add_row(vec![
+            r.to_string(),
+            count.to_string(),
+            format!("{:.4}%", count as f64 / n * 100.0),
+        ]);
+    }
+    if let Some(mode) = argmax(results.shoe_refaits.values()) {
+        table.highlight_row(mode);
+    }
+    out.push_str(&table.render_styled(color));
+    out
+}
+
+/// The couleur and inverse bets as dealt, against the exact odds, and how
+/// far apart the two came out. The exact odds give both bets the same
+/// chances, so only the simulation can show a difference.
+fn format_colors(
+    exact: &[f64; Outcome::ALL.len()],
+    results: &CoupResults,
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Couleur and Inverse ---", color));
+    out.push('\n');
+    let edge_headers: Vec<String> = rules.iter().map(|rule| format!("Edge, {}", rule)).collect();
+    let mut columns = vec![
+        ("Bet", Align::Left),
+        ("Wins", Align::Right),
+        ("Simulated", Align::Right),
+        ("Exact", Align::Right),
+    ];
+    columns.extend(edge_headers.iter().map(|h| (h.as_str(), Align::Right)));
+    let mut table = Table::new(&columns);
+    for bet in [Bet::Couleur, Bet::Inverse] {
+        let odds = results.color_odds(bet, payouts);
+        let wins = match bet {
+            Bet::Couleur => results.couleur_wins,
+            _ => results.inverse_wins,
+        };
+        let mut row = vec![
+            bet.name().to_string(),
+            wins.to_string(),
+            format!("{:.4}%", odds.win * 100.0),
+            format!("{:.4}%", bet.odds(exact, payouts).win * 100.0),
+        ];
+        row.extend(rules.iter().map(|&rule| format!("{:.4}%", odds.house_edge(rule) * 100.0)));
+        table.add_row(row);
+    }
+    out.push_str(&table.render());
+    // No exact difference is derived, and the report says so.
+    let n = results.coups as f64;
+    let (couleur, inverse) = (results.couleur_wins as f64 / n, results.inverse_wins as f64 / n);
+    let std_err = ((couleur + inverse - (couleur - inverse).powi(2)) / n).sqrt();
+    out.push_str(&format!(
+        "Couleur - inverse, as dealt: {:+.4}% ± {:.4}%\n",
+        (couleur - inverse) * 100.0,
+        std_err * 100.0
+    ));
+    out.push_str(
+        "The exact odds treat couleur and inverse as symmetric, the first card's color\n\
+         independent of the totals; any asymmetry the refait causes is left to the\n\
+         simulation above.\n",
+    );
+    out
+}
+
+/// Expected return, house edge, return to player, Kelly fraction and the
+/// value of insurance for every bet under each of `rules`, from the exact
+/// outcome probabilities. Insurance is valued net of `premium` if one is
+/// given, and otherwise by the premium at which it breaks even.
+fn format_bets(
+    exact: &[f64; Outcome::ALL.len()],
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    premium: Option<f64>,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Expected Value per Bet ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Bet", Align::Left),
+        ("Pays", Align::Right),
+        ("Refait", Align::Left),
+        ("Return", Align::Right),
+        ("House edge", Align::Right),
+        ("RTP", Align::Right),
+        ("Kelly", Align::Right),
+        (if premium.is_some() { "Insurance" } else { "Break-even premium" }, Align::Right),
+    ]);
+    let mut best: Option<(f64, Bet, RefaitRule, f64)> = None;
+    for bet in Bet::ALL {
+        for &rule in rules {
+            let odds = bet.odds(exact, payouts);
+            let breakeven = odds.insurance(rule, 0.0);
+            let insurance = odds.insurance(rule, premium.unwrap_or(0.0));
+            if best.is_none_or(|(value, ..)| insurance > value) {
+                best = Some((insurance, bet, rule, breakeven));
+            }
+            table.add_row(vec![
+                bet.name().to_string(),
+                format!("{}:1", odds.payout),
+                rule.to_string(),
+                format!("{:+.4}%", odds.expected_return(rule) * 100.0),
+                format!("{:.4}%", odds.house_edge(rule) * 100.0),
+                format!("{:.4}%", odds.rtp(rule) * 100.0),
+                format!("{:.4}%", odds.kelly(rule) * 100.0),
+                match premium {
+                    Some(_) => format!("{:+.4}%", insurance * 100.0),
+                    None => format!("{:.4}%", breakeven * 100.0),
+                },
+            ]);
+        }
+    }
+    out.push_str(&table.render());
+    match (best, premium) {
+        (Some((value, bet, rule, breakeven)), Some(premium)) => {
+            let verdict = if value > 0.0 { "pays the player" } else { "never pays the player" };
+            out.push_str(&format!(
+                "Insurance at a {}% premium {}: at best {:+.4}% a unit ({}, refait {}), \
+                 which breaks even at a {:.4}% premium\n",
+                premium * 100.0,
+                verdict,
+                value * 100.0,
+                bet.name(),
+                rule,
+                breakeven * 100.0
+            ));
+        }
+        (Some((_, bet, rule, breakeven)), None) => {
+            out.push_str(&format!(
+                "Insurance breaks even at a premium of at most {:.4}% ({}, refait {}); \
+                 pass --insurance PCT to value it at the premium a house quotes\n",
+                breakeven * 100.0,
+                bet.name(),
+                rule
+            ));
+        }
+        (None, _) => {}
+    }
+    out
+}
+
+/// Mean and standard deviation of the net result of every bet under each
+/// of `rules` over a session of `coups` coups at `stake` a coup.
+fn format_session(
+    exact: &[f64; Outcome::ALL.len()],
+    rules: &[RefaitRule],
+    payouts: &Payouts,
+    coups: u64,
+    stake: f64,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold(
+        &format!("--- Session of {} coups at {} a coup ---", coups, stake),
+        color,
+    ));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Bet", Align::Left),
+        ("Refait", Align::Left),
+        ("Expected net", Align::Right),
+        ("Std dev", Align::Right),
+        ("Std dev per coup", Align::Right),
+    ]);
+    for bet in Bet::ALL {
+        for &rule in rules {
+            let odds = bet.odds(exact, payouts);
+            let (mean, std_dev) = odds.session(rule, coups, stake);
+            table.add_row(vec![
+                bet.name().to_string(),
+                rule.to_string(),
+                format!("{:+.2}", mean),
+                format!("{:.2}", std_dev),
+                format!("{:.4}", stake * odds.variance(rule).sqrt()),
+            ]);
+        }
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// Formats the outcome table of a run dealt to `penetration` of each shoe.
+fn format_report(results: &CoupResults, penetration: f64, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Coup Outcomes ---", color));
+    out.push('\n');
+    out.push_str(&format!(
+        "Coups dealt: {} from {} shoes, cut at {}%\n",
+        results.coups,
+        results.shoes,
+        penetration * 100.0
+    ));
+    let mut table = Table::new(&[
+        ("Outcome", Align::Left),
+        ("Count", Align::Right),
+        ("Probability", Align::Right),
+    ]);
+    for (outcome, &count) in Outcome::ALL.iter().zip(&results.counts) {
+        let prob = count as f64 / results.coups as f64 * 100.0;
+        table.add_row(vec![
+            outcome.name().to_string(),
+            count.to_string(),
+            format!("{:.6}%", prob),
+        ]);
+    }
+    if let Some(mode) = argmax(results.counts.iter()) {
+        table.highlight_row(mode);
+    }
+    out.push_str(&table.render_styled(color));
+    out
+}
+
+/// Checks that every shuffle of `deck` deals at least one coup, or a run
+/// would never end, and that the row totals fit a `u8`.
+pub fn check_coup_deck(deck: &Deck, threshold: u8) -> Result<()> {
+    if deck.is_empty() {
+        return Err(Error::Invalid("The deck has no cards".to_string()));
+    }
+    // The first row stops by threshold + the highest card, the second needs
+    // threshold + 1.
+    let points: usize = deck.cards().iter().map(|&c| c as usize).sum();
+    if points < 2 * threshold as usize + deck.max_value() as usize + 1 {
+        return Err(Error::Invalid(
+            "The deck is too small to deal both rows of a coup".to_string(),
+        ));
+    }
+    sim::check_game_width(deck, &SumThreshold(threshold.into()))
+}
+
+/// `coup`: deals full two-row coups and reports how they were settled.
+pub fn run(args: CoupArgs, color: bool) -> Result<()> {
+    let deck = match args.values {
+        Some(values) => args.deck.with_values(values),
+        None => args.deck,
+    };
+    let full = if args.aces_high { deck.with_ace_value(ACE_HIGH) } else { deck };
+    let deck = match &args.remove {
+        Some(removed) => full
+            .without(removed)
+            .map_err(|e| Error::Invalid(format!("--remove: {}", e)))?,
+        None => full.clone(),
+    };
+    if args.games == 0 {
+        return Err(Error::Invalid("--games must be at least 1".to_string()));
+    }
+    if args.insurance.is_some_and(|pct| !(pct.is_finite() && pct >= 0.0)) {
+        return Err(Error::Invalid("--insurance must be a non-negative percentage".to_string()));
+    }
+    if !(args.stake.is_finite() && args.stake > 0.0) {
+        return Err(Error::Invalid("--stake must be a positive amount".to_string()));
+    }
+    let thresholds = Thresholds {
+        noir: args.threshold,
+        rouge: args.rouge_threshold.unwrap_or(args.threshold),
+    };
+    // Either row may draw to the higher total, so check the deck for both.
+    let deepest = thresholds.noir.max(thresholds.rouge);
+    check_coup_deck(&deck, deepest)?;
+    if let Some(burn) = args.burn {
+        if let Burn::Rank(rank) = burn {
+            if deck.counts()[rank_index(rank)] == 0 {
+                return Err(Error::Invalid(format!("--burn {}: the deck has no card of that rank", rank)));
+            }
+        }
+        for (left, _) in burned_decks(&deck, burn) {
+            check_coup_deck(&left, deepest)?;
+        }
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        info!("Using random seed {} (pass --seed {} to reproduce this run).", seed, seed);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let results = play_coups(&deck, thresholds, args.burn, args.penetration, args.games, &mut rng);
+
+    let unburned = exact_outcomes(&deck, thresholds)?;
+    // The report describes the game as dealt, burn and all.
+    let dealt = match args.burn {
+        Some(burn) => burned_outcomes(&deck, thresholds, burn)?,
+        None => unburned,
+    };
+    let exact = dealt.outcomes;
+    let full_outcomes = match args.remove {
+        Some(_) => Some(exact_outcomes(&full, thresholds)?.outcomes),
+        None => None,
+    };
+    let rules = args.refait.map_or(RefaitRule::ALL.to_vec(), |rule| vec![rule]);
+    let premium = args.insurance.map(|pct| pct / 100.0);
+    let report = |color| {
+        let mut report = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            format_report(&results, args.penetration, color),
+            format_refait(&dealt, &results, color),
+            format_shoe_refaits(&exact, &results, color),
+            format_house_edge(&exact, &results, &rules, &args.payouts, color),
+            format_colors(&exact, &results, &rules, &args.payouts, color),
+            format_bets(&exact, &rules, &args.payouts, premium, color)
+        );
+        if let Some(coups) = args.session {
+            report.push('\n');
+            report.push_str(&format_session(&exact, &rules, &args.payouts, coups, args.stake, color));
+        }
+        if thresholds.rouge != thresholds.noir {
+            report.push('\n');
+            report.push_str(&format_asymmetry(thresholds, &exact, &rules, &args.payouts, color));
+        }
+        if let (Some(removed), Some(full)) = (&args.remove, &full_outcomes) {
+            report.push('\n');
+            report.push_str(&format_removed(removed, full, &unburned.outcomes, &rules, &args.payouts, color));
+        }
+        if let Some(burn) = args.burn {
+            report.push('\n');
+            report.push_str(&format_burn(
+                burn,
+                &unburned.outcomes,
+                &exact,
+                &results,
+                &rules,
+                &args.payouts,
+                color,
+            ));
+        }
+        report
+    };
+
+    print!("{}", report(color));
+    if let Some(path) = &args.output {
+        fs::write(path, report(false))
+            .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+        info!("Report saved to '{}'", path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn six_deck_edges_rest_on_the_exact_refait_chance() {
+        let deck = Deck::preset("six-deck").expect("built-in preset");
+        let thresholds = Thresholds { noir: DEFAULT_THRESHOLD, rouge: DEFAULT_THRESHOLD };
+        let exact = exact_outcomes(&deck, thresholds).expect("the six-deck shoe solves");
+        let refait = exact.dependent.expect("the six-deck refait hands pair up");
+        let odds = Bet::Noir.odds(&exact.outcomes, &Payouts::default());
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(refait, 0.021_891_370_487), "{}", refait);
+        // Noir and Rouge win alike, so a half-lost refait is the whole edge.
+        assert!(close(odds.house_edge(RefaitRule::Half), refait / 2.0));
+        assert!(close(odds.house_edge(RefaitRule::Prison), 0.011_208_375_965));
+        assert!(close(odds.house_edge_decided(RefaitRule::Half), 0.011_999_738_536));
+        assert!(close(odds.house_edge_decided(RefaitRule::Prison), 0.012_287_725_986));
+    }
+}
@@ -0,0 +1,710 @@
+use crate::rule::StoppingRule;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Most ranks a deck can have. Every per-rank array, counts and values
+/// alike, holds rank `rank(i)` at index `i`, from the aces at 0 up. Convert
+/// through `rank` and `rank_index` rather than adding or subtracting 1.
+///
+/// The classic decks use ten ranks, folding the face cards into the tens;
+/// a deck uses only the ranks that hold cards (see `Deck::ranks`), so the
+/// same arrays carry a six-rank deck like a die's faces or a thirteen-rank
+/// deck that scores the face cards 11 to 13. The solvers loop over all
+/// `RANKS` and skip the empty ones.
+pub const RANKS: usize = 13;
+
+/// Index of the tens, which in the classic decks also hold the jacks,
+/// queens and kings: every face card scores ten, so a 52-card deck has
+/// sixteen cards here.
+pub const TENS: usize = rank_index(10);
+
+/// The rank held at `index` of a per-rank array.
+pub const fn rank(index: usize) -> u8 {
+    assert!(index < RANKS, "rank index out of range");
+    (index + 1) as u8
+}
+
+/// The index of `rank` in a per-rank array; `rank` must be in `1..=RANKS`.
+pub const fn rank_index(rank: u8) -> usize {
+    assert!(rank >= 1 && rank as usize <= RANKS, "rank out of range");
+    rank as usize - 1
+}
+
+/// Points scored by each rank unless `--values` says otherwise: its face value.
+pub const DEFAULT_VALUES: [u8; RANKS] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
+
+// Each index scores its own rank by default, and `rank` and `rank_index`
+// undo each other, so the solvers may read a rank's face value either way.
+const _: () = {
+    let mut i = 0;
+    while i < RANKS {
+        assert!(DEFAULT_VALUES[i] == rank(i) && rank_index(rank(i)) == i);
+        i += 1;
+    }
+};
+
+/// Draw weight of every rank unless `--weights` says otherwise: a fair
+/// shuffle, in which every card left is as likely to come next.
+pub const FAIR_WEIGHTS: [f64; RANKS] = [1.0; RANKS];
+
+/// Draw weight of each point value, indexed by the points a card scores as
+/// `Deck::cards` lists it, the jokers under `JOKER`.
+pub type PointWeights = [f64; u8::MAX as usize + 1];
+
+/// Cards of each rank in one real 52-card deck as Trente-et-Quarante scores
+/// it: four suits of ace to nine, and in each suit a ten, jack, queen and
+/// king that all score ten, so sixteen tens.
+const STANDARD_DECK: [u16; 10] = [4, 4, 4, 4, 4, 4, 4, 4, 4, 16];
+
+/// Cards in one real deck, all counted in `STANDARD_DECK`.
+const STANDARD_DECK_CARDS: usize = 52;
+
+const _: () = {
+    let (mut i, mut cards) = (0, 0);
+    while i < STANDARD_DECK.len() {
+        cards += STANDARD_DECK[i] as usize;
+        i += 1;
+    }
+    assert!(cards == STANDARD_DECK_CARDS && STANDARD_DECK.len() == TENS + 1);
+};
+
+/// Points an ace scores when counted high, as by `--aces-high`.
+pub const ACE_HIGH: u8 = 11;
+
+/// The point value `Deck::cards` gives a joker. No other card scores 0, so
+/// a row dealt from the list can tell the jokers apart and score each as
+/// its `JokerValue` says.
+pub const JOKER: u8 = 0;
+
+/// The most a joker scores when it picks its own value, as much as a ten.
+pub const JOKER_MAX: u8 = 10;
+
+/// How a joker scores when it is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JokerValue {
+    /// Always these points.
+    Fixed(u8),
+    /// The points that bring the row nearest the lowest total its rule
+    /// stops on, from 1 to `JOKER_MAX`: exactly onto it when in reach, so
+    /// a row one ten short stops on 31 in the classic game, else `JOKER_MAX`.
+    /// Under a rule with no threshold it scores 1.
+    Best,
+}
+
+impl JokerValue {
+    /// The points a joker drawn onto a row holding `sum` scores under `rule`.
+    /// It depends on nothing else, so the exact solvers may memoize it.
+    pub fn points(self, sum: u16, rule: &dyn StoppingRule) -> u8 {
+        match self {
+            JokerValue::Fixed(points) => points,
+            JokerValue::Best => match rule.sum_limit() {
+                Some(limit) => (limit + 1).saturating_sub(sum).clamp(1, JOKER_MAX as u16) as u8,
+                None => 1,
+            },
+        }
+    }
+
+    /// The most points the joker can score.
+    pub fn max_points(self) -> u8 {
+        match self {
+            JokerValue::Fixed(points) => points,
+            JokerValue::Best => JOKER_MAX,
+        }
+    }
+}
+
+/// Parses a `--joker-value` argument: `best`, or the points every joker
+/// scores.
+pub fn parse_joker_value(arg: &str) -> Result<JokerValue, String> {
+    if arg == "best" {
+        return Ok(JokerValue::Best);
+    }
+    arg.parse()
+        .ok()
+        .filter(|&points| points > 0)
+        .map(JokerValue::Fixed)
+        .ok_or_else(|| format!("expected best or a point value in 1..=255, found '{}'", arg))
+}
+
+/// The named decks accepted by `--deck`, with a short description each.
+pub const PRESETS: &[(&str, &str)] = &[
+    ("spanish-40", "40 cards: four each of 1-7 and twelve 10s (three face cards per suit)"),
+    ("standard-40", "40 cards: four each of 1-10, no face cards"),
+    ("standard-52", "52 cards: four each of 1-9 and sixteen 10s"),
+    ("six-deck", "312 cards: six 52-card decks, the classic Trente-et-Quarante shoe"),
+    ("eight-deck", "416 cards: eight 52-card decks, as some casinos deal"),
+    ("thirteen-52", "52 cards: four each of 1-13, the face cards scoring 11, 12 and 13"),
+    ("dice-36", "36 cards: six each of 1-6, the faces of six dice"),
+    ("primes-24", "24 cards: six each of 2, 3, 5 and 7, on the four lowest ranks"),
+];
+
+/// A deck described by how many cards of each rank it holds, and how many
+/// points each rank scores.
+///
+/// In config files a deck is a preset name, a deck file, or an array of up
+/// to `RANKS` counts from the aces up, e.g. `deck = [4, 4, 4, 4, 4, 4, 4, 0, 0, 12]`.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "DeckSpec")]
+pub struct Deck {
+    counts: [u16; RANKS],
+    values: [u8; RANKS],
+    /// Jokers shuffled in with the ranked cards, scoring `joker_value`.
+    jokers: u16,
+    joker_value: JokerValue,
+    /// If the aces count as `rule::SoftAces`, how many points past the
+    /// rule's threshold a row may run with an ace counted high.
+    soft_aces: Option<u16>,
+    /// How likely each card of a rank is to come next, against the other
+    /// cards left: a card of weight 2 twice as likely as one of weight 1.
+    weights: [f64; RANKS],
+}
+
+impl Deck {
+    /// Builds a deck from per-rank counts, index `i` being rank `rank(i)`.
+    pub fn from_counts(counts: [u16; RANKS]) -> Self {
+        Deck {
+            counts,
+            values: DEFAULT_VALUES,
+            jokers: 0,
+            joker_value: JokerValue::Best,
+            soft_aces: None,
+            weights: FAIR_WEIGHTS,
+        }
+    }
+
+    /// Builds a deck from `value -> count` pairs; values left out have no cards.
+    /// When every value is a rank, each card keeps its own rank, so `--values`
+    /// finds it there; otherwise the deck is built by `from_points`.
+    pub fn from_map(map: &BTreeMap<u8, u16>) -> Result<Self, String> {
+        if !map.keys().all(|value| (1..=RANKS as u8).contains(value)) {
+            return Deck::from_points(map);
+        }
+        let mut counts = [0; RANKS];
+        for (&value, &count) in map {
+            counts[rank_index(value)] = count;
+        }
+        Ok(Deck::from_counts(counts))
+    }
+
+    /// Builds a deck from `points -> count` pairs whose points need not be
+    /// ranks nor follow one another, such as the primes 2, 3, 5 and 7: the
+    /// lowest points go on the aces, the next on the twos, and so on, each
+    /// rank scoring its own points. Fails on 0 points or more than `RANKS`
+    /// distinct values.
+    pub fn from_points(map: &BTreeMap<u8, u16>) -> Result<Self, String> {
+        if map.len() > RANKS {
+            return Err(format!(
+                "{} distinct card values given, but a deck has at most {} ranks",
+                map.len(),
+                RANKS
+            ));
+        }
+        if map.contains_key(&0) {
+            return Err("card value 0 scores no points".to_string());
+        }
+        let (mut counts, mut values) = ([0; RANKS], DEFAULT_VALUES);
+        for (i, (&points, &count)) in map.iter().enumerate() {
+            counts[i] = count;
+            values[i] = points;
+        }
+        Ok(Deck::from_counts(counts).with_values(values))
+    }
+
+    /// Reads a deck file. `.json` and `.toml` files hold a map from value to
+    /// count; anything else has one `value=count` per line, with blank lines
+    /// and `#` comments ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read deck file '{}': {}", path.display(), e))?;
+        let map = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                let raw: BTreeMap<String, u16> =
+                    serde_json::from_str(&text).map_err(|e| e.to_string())?;
+                parse_keys(raw)
+            }
+            Some("toml") => {
+                let raw: BTreeMap<String, u16> =
+                    toml::from_str(&text).map_err(|e| e.to_string())?;
+                parse_keys(raw)
+            }
+            _ => parse_lines(&text),
+        };
+        map.and_then(|map| Deck::from_map(&map))
+            .map_err(|e| format!("invalid deck file '{}': {}", path.display(), e))
+    }
+
+    /// Reads a deck from standard input until EOF: a JSON map if the input
+    /// starts with `{`, otherwise `value=count` lines as in a deck file.
+    pub fn read_stdin() -> Result<Self, String> {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("cannot read deck from stdin: {}", e))?;
+        let map = if text.trim_start().starts_with('{') {
+            serde_json::from_str(&text)
+                .map_err(|e| e.to_string())
+                .and_then(parse_keys)
+        } else {
+            parse_lines(&text)
+        };
+        map.and_then(|map| Deck::from_map(&map))
+            .map_err(|e| format!("invalid deck on stdin: {}", e))
+    }
+
+    /// Builds a deck from the counts of its lowest ranks, `counts[i]` being
+    /// rank `rank(i)`; the ranks after them have no cards. Fails on more
+    /// than `RANKS` counts.
+    pub fn from_ranks(counts: &[u16]) -> Result<Self, String> {
+        if counts.len() > RANKS {
+            return Err(format!(
+                "{} card counts given, but a deck has at most {} ranks",
+                counts.len(),
+                RANKS
+            ));
+        }
+        let mut all = [0; RANKS];
+        all[..counts.len()].copy_from_slice(counts);
+        Ok(Deck::from_counts(all))
+    }
+
+    /// `n` real 52-card decks shuffled together, scored the Trente-et-Quarante
+    /// way: aces 1, two to nine their face value, and ten, jack, queen and
+    /// king all 10. The face cards fold into the tens, so this is 4n each of
+    /// 1-9 and 16n tens, 52n cards. Fails if the tens overflow a count.
+    pub fn from_standard_decks(n: u16) -> Result<Self, String> {
+        let mut counts = [0; 10];
+        for (count, &per_deck) in counts.iter_mut().zip(&STANDARD_DECK) {
+            *count = per_deck
+                .checked_mul(n)
+                .ok_or_else(|| format!("{} decks hold more than {} cards of a rank", n, u16::MAX))?;
+        }
+        let deck = Deck::from_ranks(&counts)?;
+        debug_assert_eq!(deck.len(), STANDARD_DECK_CARDS * n as usize);
+        Ok(deck)
+    }
+
+    /// Looks up one of the `PRESETS` by name.
+    pub fn preset(name: &str) -> Option<Self> {
+        let deck = match name {
+            "standard-52" => Deck::from_standard_decks(1),
+            "six-deck" => Deck::from_standard_decks(6),
+            "eight-deck" => Deck::from_standard_decks(8),
+            "primes-24" => Deck::from_points(&BTreeMap::from([(2, 6), (3, 6), (5, 6), (7, 6)])),
+            _ => return Deck::preset_counts(name),
+        };
+        Some(deck.expect("presets fit in RANKS"))
+    }
+
+    /// The `PRESETS` given by their counts alone.
+    fn preset_counts(name: &str) -> Option<Self> {
+        let counts: &[u16] = match name {
+            "spanish-40" => &[4, 4, 4, 4, 4, 4, 4, 0, 0, 12],
+            "standard-40" => &[4; 10],
+            "thirteen-52" => &[4; 13],
+            "dice-36" => &[6; 6],
+            _ => return None,
+        };
+        Some(Deck::from_ranks(counts).expect("presets fit in RANKS"))
+    }
+
+    /// Cards of each rank, index `i` being rank `rank(i)`.
+    pub fn counts(&self) -> [u16; RANKS] {
+        self.counts
+    }
+
+    /// Takes `count` cards of `rank` out of the deck, as when they have been
+    /// dealt. Fails, leaving the deck alone, on a rank outside 1..=`RANKS`
+    /// or more cards than the deck holds of it.
+    pub fn remove(&mut self, rank: u8, count: u16) -> Result<(), String> {
+        if !(1..=RANKS as u8).contains(&rank) {
+            return Err(format!("rank {} is not in 1..={}", rank, RANKS));
+        }
+        let held = &mut self.counts[rank_index(rank)];
+        if count > *held {
+            return Err(format!(
+                "cannot remove {} cards of rank {}: the deck holds {}",
+                count, rank, held
+            ));
+        }
+        *held -= count;
+        Ok(())
+    }
+
+    /// The deck left once the `removed` cards, `rank -> count`, are dealt
+    /// out of it, as from a shoe part way through. Fails as `remove` does.
+    pub fn without(&self, removed: &BTreeMap<u8, u16>) -> Result<Self, String> {
+        let mut left = self.clone();
+        for (&rank, &count) in removed {
+            left.remove(rank, count)?;
+        }
+        Ok(left)
+    }
+
+    /// The same cards, scoring `values[i]` points for rank `rank(i)`.
+    pub fn with_values(mut self, values: [u8; RANKS]) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// The same cards with the aces scoring `points`, whatever they scored
+    /// before.
+    pub fn with_ace_value(mut self, points: u8) -> Self {
+        self.values[rank_index(1)] = points;
+        self
+    }
+
+    /// The same cards with `jokers` jokers added, each scoring `value`.
+    pub fn with_jokers(mut self, jokers: u16, value: JokerValue) -> Self {
+        self.jokers = jokers;
+        self.joker_value = value;
+        self
+    }
+
+    /// Jokers in the deck, on top of the cards in `counts`.
+    pub fn jokers(&self) -> u16 {
+        self.jokers
+    }
+
+    /// How the jokers score.
+    pub fn joker_value(&self) -> JokerValue {
+        self.joker_value
+    }
+
+    /// The same cards with the aces counting as soft totals, high or low as
+    /// `rule::SoftAces` says, busting `reach` points past the threshold; or
+    /// with `None`, at their own points again.
+    pub fn with_soft_aces(mut self, reach: Option<u16>) -> Self {
+        self.soft_aces = reach;
+        self
+    }
+
+    /// Whether the aces count as soft totals.
+    pub fn soft_aces(&self) -> bool {
+        self.soft_aces.is_some()
+    }
+
+    /// How many points past the threshold a row may run with a soft ace
+    /// counted high, if the aces count soft.
+    pub fn soft_reach(&self) -> Option<u16> {
+        self.soft_aces
+    }
+
+    /// The same cards drawn with each card of rank `rank(i)` weighing
+    /// `weights[i]`, as with a shuffle biased toward some ranks.
+    pub fn with_weights(mut self, weights: [f64; RANKS]) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// Draw weight of each rank, index `i` being rank `rank(i)`.
+    pub fn weights(&self) -> [f64; RANKS] {
+        self.weights
+    }
+
+    /// Whether some rank weighs other than the rest, so the draw is not a
+    /// fair one. Ranks without cards do not count.
+    pub fn is_weighted(&self) -> bool {
+        let mut held = (0..RANKS).filter(|&i| self.counts[i] > 0).map(|i| self.weights[i]);
+        let first = held.next().unwrap_or(1.0);
+        held.any(|w| w != first) || (self.jokers > 0 && first != 1.0)
+    }
+
+    /// Draw weight of each card by the points it scores, for drawing from
+    /// `cards`; jokers weigh 1. Fails if two ranks in the deck score alike
+    /// but weigh differently, as the points alone cannot tell them apart.
+    pub fn point_weights(&self) -> Result<PointWeights, String> {
+        let mut weights = [1.0; u8::MAX as usize + 1];
+        let mut weighed = [false; u8::MAX as usize + 1];
+        for i in (0..RANKS).filter(|&i| self.counts[i] > 0) {
+            let points = self.values[i] as usize;
+            if weighed[points] && weights[points] != self.weights[i] {
+                return Err(format!(
+                    "two ranks score {} points but weigh differently, which the \
+                     simulation cannot tell apart",
+                    points
+                ));
+            }
+            weights[points] = self.weights[i];
+            weighed[points] = true;
+        }
+        Ok(weights)
+    }
+
+    /// Points a soft ace adds by counting high, if the deck holds soft aces.
+    fn soft_bonus(&self) -> Option<u8> {
+        let aces = rank_index(1);
+        (self.soft_aces() && self.counts[aces] > 0)
+            .then(|| ACE_HIGH.saturating_sub(self.values[aces]))
+    }
+
+    /// Points scored by each rank, index `i` being rank `rank(i)`.
+    pub fn values(&self) -> [u8; RANKS] {
+        self.values
+    }
+
+    /// The number of ranks in play: the highest rank holding any cards, so
+    /// 10 for the classic decks and 6 for `dice-36`. 0 for an empty deck.
+    pub fn ranks(&self) -> usize {
+        self.counts.iter().rposition(|&count| count > 0).map_or(0, |i| i + 1)
+    }
+
+    /// The most points any card in the deck scores, a joker or a soft ace
+    /// as many as it can; 0 for an empty deck.
+    pub fn max_value(&self) -> u8 {
+        let jokers = (self.jokers > 0).then(|| self.joker_value.max_points());
+        let soft_ace = self
+            .soft_bonus()
+            .map(|bonus| self.values[rank_index(1)].saturating_add(bonus));
+        self.counts
+            .iter()
+            .zip(self.values)
+            .filter(|&(&count, _)| count > 0)
+            .map(|(_, value)| value)
+            .chain(jokers)
+            .chain(soft_ace)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The points of the whole deck, each joker scoring as many as it can
+    /// and one soft ace, the most that count high at once, counting high.
+    pub fn max_points(&self) -> u32 {
+        let ranked: u32 = self.counts.iter().zip(self.values).map(|(&c, v)| c as u32 * v as u32).sum();
+        ranked
+            + self.jokers as u32 * self.joker_value.max_points() as u32
+            + self.soft_bonus().map_or(0, u32::from)
+    }
+
+    /// Total number of cards, jokers included.
+    pub fn len(&self) -> usize {
+        self.counts.iter().map(|&c| c as usize).sum::<usize>() + self.jokers as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every card's point value, in ascending order, with a `JOKER` for
+    /// each joker.
+    pub fn cards(&self) -> Vec<u8> {
+        let mut cards = Vec::with_capacity(self.len());
+        cards.extend(std::iter::repeat_n(JOKER, self.jokers as usize));
+        for (&count, &value) in self.counts.iter().zip(&self.values) {
+            cards.extend(std::iter::repeat_n(value, count as usize));
+        }
+        cards.sort_unstable();
+        cards
+    }
+}
+
+impl FromStr for Deck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Deck::preset(s).ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|&(name, _)| name).collect();
+            format!(
+                "unknown deck '{}' (expected a deck file or one of: {})",
+                s,
+                names.join(", ")
+            )
+        })
+    }
+}
+
+/// Parses a `--deck` argument: a preset name, `-` for standard input, or
+/// else the path of a deck file.
+pub fn parse_arg(arg: &str) -> Result<Deck, String> {
+    if arg == "-" {
+        return Deck::read_stdin();
+    }
+    if let Some(deck) = Deck::preset(arg) {
+        return Ok(deck);
+    }
+    let path = Path::new(arg);
+    if path.exists() {
+        Deck::load(path)
+    } else {
+        arg.parse()
+    }
+}
+
+/// Parses a `--values` argument: comma-separated `rank=points` pairs, each
+/// overriding the face value of one rank, e.g. `1=11` for aces worth 11.
+pub fn parse_values(arg: &str) -> Result<[u8; RANKS], String> {
+    let mut values = DEFAULT_VALUES;
+    for pair in arg.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (rank, points) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected rank=points, found '{}'", pair))?;
+        let rank: u8 = rank
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a rank", rank.trim()))?;
+        if !(1..=RANKS as u8).contains(&rank) {
+            return Err(format!("rank {} is not in 1..={}", rank, RANKS));
+        }
+        let points: u8 = points
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| format!("'{}' is not a point value in 1..=255", points.trim()))?;
+        values[rank_index(rank)] = points;
+    }
+    Ok(values)
+}
+
+/// Parses a `--remove` argument: comma-separated `rank=count` pairs, each
+/// naming cards to take out of the deck, e.g. `1=24` for every ace of a
+/// six-deck shoe.
+pub fn parse_removed(arg: &str) -> Result<BTreeMap<u8, u16>, String> {
+    let mut removed = BTreeMap::new();
+    for pair in arg.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (rank, count) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected rank=count, found '{}'", pair))?;
+        let rank: u8 = rank
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a rank", rank.trim()))?;
+        let count: u16 = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a card count", count.trim()))?;
+        if removed.insert(rank, count).is_some() {
+            return Err(format!("rank {} is listed twice", rank));
+        }
+    }
+    Ok(removed)
+}
+
+/// Parses a `--weights` argument: comma-separated `rank=weight` pairs, each
+/// making the cards of one rank that much likelier to be drawn than those
+/// of weight 1, e.g. `10=1.05` for a shuffle leaning 5% toward the tens.
+pub fn parse_weights(arg: &str) -> Result<[f64; RANKS], String> {
+    let mut weights = FAIR_WEIGHTS;
+    for pair in arg.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (rank, weight) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected rank=weight, found '{}'", pair))?;
+        let rank: u8 = rank
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a rank", rank.trim()))?;
+        if !(1..=RANKS as u8).contains(&rank) {
+            return Err(format!("rank {} is not in 1..={}", rank, RANKS));
+        }
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .ok()
+            .filter(|w: &f64| w.is_finite() && *w > 0.0)
+            .ok_or_else(|| format!("'{}' is not a positive weight", weight.trim()))?;
+        weights[rank_index(rank)] = weight;
+    }
+    Ok(weights)
+}
+
+/// Parses the value names of a JSON or TOML deck map.
+fn parse_keys(raw: BTreeMap<String, u16>) -> Result<BTreeMap<u8, u16>, String> {
+    let mut map = BTreeMap::new();
+    for (key, count) in raw {
+        let value: u8 = key
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a card value", key))?;
+        if map.insert(value, count).is_some() {
+            return Err(format!("card value {} is listed twice", value));
+        }
+    }
+    Ok(map)
+}
+
+/// Parses the line-based `value=count` deck format.
+fn parse_lines(text: &str) -> Result<BTreeMap<u8, u16>, String> {
+    let mut map = BTreeMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: String| format!("line {}: {}", i + 1, msg);
+        let (value, count) = line
+            .split_once('=')
+            .ok_or_else(|| err(format!("expected value=count, found '{}'", line)))?;
+        let value: u8 = value
+            .trim()
+            .parse()
+            .map_err(|_| err(format!("'{}' is not a card value", value.trim())))?;
+        let count: u16 = count
+            .trim()
+            .parse()
+            .map_err(|_| err(format!("'{}' is not a card count", count.trim())))?;
+        if map.insert(value, count).is_some() {
+            return Err(err(format!("card value {} is listed twice", value)));
+        }
+    }
+    Ok(map)
+}
+
+/// The two ways a config file may spell a deck.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DeckSpec {
+    Preset(String),
+    Counts(Vec<u16>),
+}
+
+impl TryFrom<DeckSpec> for Deck {
+    type Error = String;
+
+    fn try_from(spec: DeckSpec) -> Result<Self, Self::Error> {
+        match spec {
+            DeckSpec::Preset(name) => parse_arg(&name),
+            DeckSpec::Counts(counts) => Deck::from_ranks(&counts),
+        }
+    }
+}
+
+impl fmt::Display for Deck {
+    /// Formats as `points=count` pairs for the points the cards score, ranks
+    /// scoring alike counted together, e.g. `1=4,2=4,...`, then
+    /// `joker=count` if there are jokers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut by_points = BTreeMap::new();
+        for (&count, &points) in self.counts.iter().zip(&self.values).filter(|&(&c, _)| c > 0) {
+            *by_points.entry(points).or_insert(0) += count;
+        }
+        let mut parts: Vec<String> =
+            by_points.iter().map(|(points, count)| format!("{}={}", points, count)).collect();
+        if self.jokers > 0 {
+            parts.push(format!("joker={}", self.jokers));
+        }
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_lists_points_not_ranks() {
+        let primes = Deck::preset("primes-24").expect("built-in preset");
+        assert_eq!(primes.to_string(), "2=6,3=6,5=6,7=6");
+    }
+
+    #[test]
+    fn display_counts_ranks_scoring_alike_together() {
+        let deck = Deck::preset("thirteen-52").expect("built-in preset").with_values(
+            parse_values("11=10,12=10,13=10").expect("valid values"),
+        );
+        assert_eq!(deck.to_string(), "1=4,2=4,3=4,4=4,5=4,6=4,7=4,8=4,9=4,10=16");
+    }
+}
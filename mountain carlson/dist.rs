@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+/// The probability type the exact solvers memoize. With the `f32` feature
+/// it is `f32`, which halves the probabilities' share of the memo for state
+/// spaces that would not fit otherwise. An `f32` keeps about 7 significant
+/// digits to an `f64`'s 16, and the rounding accumulates over the draws of a
+/// row: the distributions then sum to 1 only to within about 1e-6 rather
+/// than 1e-12, and the smallest probabilities lose most of their digits.
+/// The distributions handed out in `ExactDist` are `f64` either way.
+#[cfg(not(feature = "f32"))]
+pub type Prob = f64;
+#[cfg(feature = "f32")]
+pub type Prob = f32;
+
+/// Widens a memoized probability for `ExactDist`.
+// Without the `f32` feature this converts `f64` to itself.
+#[allow(clippy::useless_conversion)]
+pub fn to_f64(p: Prob) -> f64 {
+    f64::from(p)
+}
+
+/// Exact distributions of one row, whichever solver produced them.
+pub struct ExactDist {
+    /// Probability of each final score.
+    pub scores: BTreeMap<u16, f64>,
+    /// Probability of each row length, in cards.
+    pub lengths: BTreeMap<u16, f64>,
+    /// Number of memoized states the solver visited.
+    pub states: usize,
+}
+
+impl ExactDist {
+    /// The expected score, or `None` if the distribution holds no
+    /// probability to average over.
+    pub fn mean_score(&self) -> Option<f64> {
+        mean(&self.scores)
+    }
+
+    /// The expected row length in cards, or `None` as for `mean_score`.
+    pub fn mean_length(&self) -> Option<f64> {
+        mean(&self.lengths)
+    }
+
+    /// These distributions, each rescaled by its total so that rounding in
+    /// the solver no longer shows: the totals come out at 1 to within the
+    /// rounding of the sum itself, rather than of every term added.
+    pub fn normalized(&self) -> ExactDist {
+        let rescale = |probs: &BTreeMap<u16, f64>| {
+            let total: f64 = probs.values().sum();
+            probs.iter().map(|(&v, &p)| (v, p / total)).collect()
+        };
+        ExactDist {
+            scores: rescale(&self.scores),
+            lengths: rescale(&self.lengths),
+            states: self.states,
+        }
+    }
+}
+
+/// The mean of a value-to-probability map, weighted by its total so that a
+/// map summing to a little off 1 still averages its values.
+fn mean(probs: &BTreeMap<u16, f64>) -> Option<f64> {
+    let total: f64 = probs.values().sum();
+    let weighted: f64 = probs.iter().map(|(&v, &p)| v as f64 * p).sum();
+    (total > 0.0).then(|| weighted / total)
+}
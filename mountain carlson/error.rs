@@ -0,0 +1,49 @@
+use std::fmt;
+
+/// Why a command failed. Each kind maps to its own process exit code so
+/// scripts can tell them apart; 0 always means success.
+#[derive(Debug)]
+pub enum Error {
+    /// Bad arguments, config or deck. Exit code 1.
+    Invalid(String),
+    /// A file could not be read or written. Exit code 2.
+    Io(String),
+    /// The computation stopped before it finished. Exit code 3.
+    Aborted(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Invalid(_) => 1,
+            Error::Io(_) => 2,
+            Error::Aborted(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(msg) | Error::Io(msg) | Error::Aborted(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The first error among independent steps that were all attempted. The
+/// later errors are printed to stderr here so none goes unreported.
+pub fn first_error(results: impl IntoIterator<Item = Result<()>>) -> Result<()> {
+    let mut first = Ok(());
+    for result in results {
+        match (&first, result) {
+            (Ok(()), Err(e)) => first = Err(e),
+            (Err(_), Err(e)) => log::error!("{}", e),
+            (_, Ok(())) => {}
+        }
+    }
+    first
+}
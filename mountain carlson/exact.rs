@@ -0,0 +1,778 @@
+use crate::error::{Error, Result};
+use crate::sim;
+use clap::{Args, ValueEnum};
+use log::{info, warn};
+use monte_carlo_sim::bet::{self, Bet, Payouts, RefaitRule};
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, Deck, JokerValue, ACE_HIGH, RANKS};
+use monte_carlo_sim::dist::ExactDist;
+use monte_carlo_sim::rule::{self, Started, StoppingRule, SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use monte_carlo_sim::table::{argmax, Align, Table};
+use monte_carlo_sim::variant::{self, Variant};
+use monte_carlo_sim::{memo_sim, packed, shoe_solver};
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Instant;
+
+/// The exact solvers `exact --solver` chooses between.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Solver {
+    /// `memo` for decks of up to 255 cards, `shoe` for larger ones and for
+    /// decks with jokers, soft aces or weights.
+    Auto,
+    /// Byte-sized counts (memo_sim.rs); fastest, at most 255 cards.
+    Memo,
+    /// 16-bit counts (512.rs), for multi-deck shoes.
+    Shoe,
+    /// Counts packed four bits per value (30_b_w.rs); at most 15 of each value.
+    Packed,
+    /// `shoe` split across `--threads` threads by the opening cards, each
+    /// thread with its own memo.
+    Sharded,
+}
+
+/// Options of the `exact` subcommand.
+#[derive(Args)]
+pub struct ExactArgs {
+    /// Stop drawing once the total exceeds this value.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_THRESHOLD,
+        value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE),
+    )]
+    threshold: u8,
+
+    /// Deck to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin.
+    #[arg(long, default_value = "spanish-40", value_parser = deck::parse_arg)]
+    deck: Deck,
+
+    /// Named game to play, setting the deck, threshold and aces together:
+    /// classic-6deck, spanish-40, simplified-40 or blackjack-21. Taken by
+    /// exact, sim and compare only.
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = variant::parse_arg,
+        conflicts_with_all = ["deck", "threshold"],
+    )]
+    variant: Option<Variant>,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Count aces as soft totals busting past BUST: 11 while that keeps the
+    /// row at or under BUST, dropping back to 1 when a later card would take
+    /// it past. A blackjack dealer is `--threshold 16 --soft-aces 21`.
+    #[arg(long, value_name = "BUST", conflicts_with = "aces_high")]
+    soft_aces: Option<u16>,
+
+    /// Jokers to shuffle into the deck.
+    #[arg(long, default_value_t = 0)]
+    jokers: u16,
+
+    /// Points a joker scores: `best` for the points that take the row
+    /// nearest the threshold's stopping total, or a fixed value.
+    #[arg(long, default_value = "best", value_parser = deck::parse_joker_value)]
+    joker_value: JokerValue,
+
+    /// Draw weight of each rank, as `rank=weight` pairs such as `10=1.05`,
+    /// for a shuffle that favors some ranks; ranks not listed weigh 1. The
+    /// report then shows how the bias moves the row and the house edge.
+    #[arg(long, value_parser = deck::parse_weights)]
+    weights: Option<[f64; RANKS]>,
+
+    /// Which solver computes the distributions.
+    #[arg(long, value_enum, default_value_t = Solver::Auto)]
+    solver: Solver,
+
+    /// Points the row already holds before its first card is drawn. The
+    /// cards that scored them should be left out of --deck, or named in
+    /// --remove.
+    #[arg(long, default_value_t = 0)]
+    start: u16,
+
+    /// Cards already dealt from the deck, as `rank=count` pairs such as
+    /// `1=4,10=12`, to solve the row from the shoe part way through.
+    #[arg(long, value_name = "RANK=COUNT", value_parser = deck::parse_removed)]
+    remove: Option<BTreeMap<u8, u16>>,
+
+    /// Threads for --solver sharded [default: one per CPU].
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=1024))]
+    threads: Option<u64>,
+
+    /// Rescale the score and length distributions to sum to 1 before
+    /// reporting them. The residual of the raw ones is logged.
+    #[arg(long)]
+    normalize: bool,
+
+    /// Fail if the score or length probabilities do not sum to 1. Debug
+    /// builds check this on every solve.
+    #[arg(long)]
+    check_mass: bool,
+
+    /// Also solve with every other solver that takes the deck, and fail
+    /// if any of their score or length distributions differs.
+    #[arg(long)]
+    cross_check: bool,
+
+    /// Also save the report to this file.
+    #[arg(long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// The solvers `--cross-check` runs, each on its own.
+const CHECKED_SOLVERS: [Solver; 4] = [Solver::Memo, Solver::Shoe, Solver::Packed, Solver::Sharded];
+
+/// How far the probabilities of a row may sum from 1 before `check_mass`
+/// fails; see `dist::Prob` for why `f32` needs more room.
+#[cfg(not(feature = "f32"))]
+const MASS_TOLERANCE: f64 = 1e-9;
+#[cfg(feature = "f32")]
+const MASS_TOLERANCE: f64 = 1e-4;
+
+/// Largest difference in any probability `--cross-check` lets pass. The
+/// solvers add the same terms in different orders, which an `f32` memo
+/// feels far more than an `f64` one.
+#[cfg(not(feature = "f32"))]
+const CROSS_CHECK_TOLERANCE: f64 = 1e-12;
+#[cfg(feature = "f32")]
+const CROSS_CHECK_TOLERANCE: f64 = 1e-5;
+
+/// Solves `deck` exactly with the chosen solver, stopping each row by `rule`.
+/// `threads` is used only by `Solver::Sharded`.
+pub fn solve(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    solver: Solver,
+    threads: u64,
+) -> Result<ExactDist> {
+    if deck.is_empty() {
+        return Err(Error::Invalid("Cannot solve: the deck has no cards".to_string()));
+    }
+    let solved = match solver {
+        Solver::Auto
+            if deck.len() > u8::MAX as usize
+                || deck.jokers() > 0
+                || deck.soft_aces()
+                || deck.is_weighted() =>
+        {
+            shoe_solver::distributions(deck, rule)
+        }
+        Solver::Auto => memo_sim::distributions(deck, rule),
+        Solver::Memo => memo_sim::distributions(deck, rule),
+        Solver::Shoe => shoe_solver::distributions(deck, rule),
+        Solver::Packed => packed::distributions(deck, rule),
+        Solver::Sharded => shoe_solver::distributions_sharded(deck, rule, threads as usize),
+    };
+    let dist = solved.map_err(|e| Error::Invalid(format!("Cannot solve: {}", e)))?;
+    if cfg!(debug_assertions) {
+        if let Err(e) = check_mass(&dist) {
+            panic!("{}", e);
+        }
+    }
+    Ok(dist)
+}
+
+/// `solve` for a row that already holds `start` points. A row the rule
+/// stops before any card ends on `start` with certainty.
+pub fn solve_from(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    start: u16,
+    solver: Solver,
+    threads: u64,
+) -> Result<ExactDist> {
+    if start == 0 {
+        return solve(deck, rule, solver, threads);
+    }
+    // No card is drawn yet, so there is no last card to show the rule.
+    if rule.should_stop(start, 0, 0) {
+        return Ok(ExactDist {
+            scores: BTreeMap::from([(start, 1.0)]),
+            lengths: BTreeMap::from([(0, 1.0)]),
+            states: 0,
+        });
+    }
+    let dist = solve(deck, &Started { rule, start }, solver, threads)?;
+    let mut scores = BTreeMap::new();
+    for (score, p) in dist.scores {
+        let score = score.checked_add(start).ok_or_else(|| {
+            Error::Invalid(format!("A row started on {} points could score past {}", start, u16::MAX))
+        })?;
+        scores.insert(score, p);
+    }
+    Ok(ExactDist { scores, ..dist })
+}
+
+/// Checks that every row ends: the score and the length probabilities
+/// each sum to 1 within `MASS_TOLERANCE`. Mass lost or gained means a
+/// solver dropped paths or counted some twice.
+pub fn check_mass(dist: &ExactDist) -> Result<()> {
+    for (label, probs) in [("score", &dist.scores), ("length", &dist.lengths)] {
+        // Folded from +0, as an empty sum would otherwise print as -0.
+        let total = probs.values().fold(0.0, |sum, p| sum + p);
+        if (total - 1.0).abs() > MASS_TOLERANCE {
+            return Err(Error::Invalid(format!(
+                "The {} probabilities sum to {:.12}, not 1: the solver lost track of some rows",
+                label, total
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The largest difference between two distributions, a value missing from
+/// one counting as probability 0.
+fn largest_difference(a: &BTreeMap<u16, f64>, b: &BTreeMap<u16, f64>) -> f64 {
+    a.keys()
+        .chain(b.keys())
+        .map(|v| (a.get(v).copied().unwrap_or(0.0) - b.get(v).copied().unwrap_or(0.0)).abs())
+        .fold(0.0, f64::max)
+}
+
+/// How far one solver's distributions are from the chosen solver's, or
+/// why it could not take the deck.
+struct SolverCheck {
+    name: String,
+    /// Largest score and length differences, and the solver's memo states.
+    result: std::result::Result<(f64, f64, usize), Error>,
+}
+
+impl SolverCheck {
+    fn agrees(&self) -> bool {
+        self.result
+            .as_ref()
+            .map_or(true, |&(scores, lengths, _)| scores.max(lengths) <= CROSS_CHECK_TOLERANCE)
+    }
+}
+
+/// Solves `deck` with every solver in `CHECKED_SOLVERS` and measures how
+/// far each is from `dist`. A solver that cannot take the deck is skipped,
+/// with the reason logged.
+fn cross_check(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    start: u16,
+    dist: &ExactDist,
+    threads: u64,
+) -> Vec<SolverCheck> {
+    CHECKED_SOLVERS
+        .iter()
+        .map(|&solver| {
+            let name = solver.to_possible_value().expect("no skipped variants").get_name().to_string();
+            let result = solve_from(deck, rule, start, solver, threads).map(|other| {
+                (
+                    largest_difference(&dist.scores, &other.scores),
+                    largest_difference(&dist.lengths, &other.lengths),
+                    other.states,
+                )
+            });
+            if let Err(e) = &result {
+                info!("Cross-check skips the {} solver. {}", name, e);
+            }
+            SolverCheck { name, result }
+        })
+        .collect()
+}
+
+/// Formats how far each solver is from the chosen one.
+fn format_cross_check(checks: &[SolverCheck], color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Cross-check of the Solvers ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Solver", Align::Left),
+        ("Score difference", Align::Right),
+        ("Length difference", Align::Right),
+        ("States", Align::Right),
+    ]);
+    for check in checks {
+        let row = match &check.result {
+            Ok((scores, lengths, states)) => vec![
+                check.name.clone(),
+                format!("{:.3e}", scores),
+                format!("{:.3e}", lengths),
+                states.to_string(),
+            ],
+            Err(_) => vec![check.name.clone(), "skipped".to_string(), "-".to_string(), "-".to_string()],
+        };
+        table.add_row(row);
+    }
+    out.push_str(&table.render());
+    if checks.iter().all(SolverCheck::agrees) {
+        out.push_str(&format!("All solvers agree to within {:e}\n", CROSS_CHECK_TOLERANCE));
+    }
+    out
+}
+
+/// Appends one distribution table with its total probability and `mean`.
+fn push_distribution(
+    out: &mut String,
+    label: &str,
+    probs: &BTreeMap<u16, f64>,
+    mean: Option<f64>,
+    color: bool,
+) {
+    let mut table = Table::new(&[(label, Align::Right), ("Probability", Align::Right)]);
+    let mut total = 0.0;
+    for (&value, &prob) in probs {
+        table.add_row(vec![value.to_string(), format!("{:.6}%", prob * 100.0)]);
+        total += prob;
+    }
+    if let Some(mode) = argmax(probs.values()) {
+        table.highlight_row(mode);
+    }
+    out.push_str(&table.render_styled(color));
+    out.push_str(&format!("Total Probability: {:.6}%\n", total * 100.0));
+    out.push_str(&format!("Residual: {:+.3e}\n", total - 1.0));
+    match mean {
+        Some(mean) => out.push_str(&format!("Average {}: {:.6}\n", label, mean)),
+        None => out.push_str(&format!("Average {}: no data, no row has an outcome\n", label)),
+    }
+}
+
+/// Formats the score and length tables with their totals and averages.
+pub fn format_report(dist: &ExactDist, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Score Distribution (Exact) ---", color));
+    out.push('\n');
+    push_distribution(&mut out, "Score", &dist.scores, dist.mean_score(), color);
+
+    out.push('\n');
+    out.push_str(&color::bold("--- Length Distribution (Exact) ---", color));
+    out.push('\n');
+    push_distribution(&mut out, "Length", &dist.lengths, dist.mean_length(), color);
+    out
+}
+
+/// How a rule variant moves the score distribution away from the base game:
+/// both distributions score by score with their difference, then both
+/// averages. `labels` name the base and the variant columns.
+pub fn format_shift(
+    title: &str,
+    labels: (&str, &str),
+    base: &ExactDist,
+    variant: &ExactDist,
+    color: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold(&format!("--- {} ---", title), color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Score", Align::Right),
+        (labels.0, Align::Right),
+        (labels.1, Align::Right),
+        ("Difference", Align::Right),
+    ]);
+    let scores: std::collections::BTreeSet<u16> =
+        base.scores.keys().chain(variant.scores.keys()).copied().collect();
+    for score in scores {
+        let p_base = base.scores.get(&score).copied().unwrap_or(0.0);
+        let p_variant = variant.scores.get(&score).copied().unwrap_or(0.0);
+        table.add_row(vec![
+            score.to_string(),
+            format!("{:.6}%", p_base * 100.0),
+            format!("{:.6}%", p_variant * 100.0),
+            format!("{:+.6}%", (p_variant - p_base) * 100.0),
+        ]);
+    }
+    out.push_str(&table.render());
+    for (label, base_mean, variant_mean) in [
+        ("score", base.mean_score(), variant.mean_score()),
+        ("length", base.mean_length(), variant.mean_length()),
+    ] {
+        if let (Some(b), Some(v)) = (base_mean, variant_mean) {
+            out.push_str(&format!(
+                "Average {}: {:.6} -> {:.6} ({:+.6})\n",
+                label,
+                b,
+                v,
+                v - b
+            ));
+        }
+    }
+    out
+}
+
+/// How the house edge on Noir moves from the `base` rows to the `variant`
+/// ones under each refait rule, at even money and with the two rows of a
+/// coup taken as independent.
+fn format_edge_shift(base: &ExactDist, variant: &ExactDist, threshold: u16) -> String {
+    let [base, variant] = [base, variant].map(|dist| {
+        Bet::Noir.odds(&bet::independent_rows(&dist.scores, threshold), &Payouts::default())
+    });
+    let mut out = String::new();
+    for rule in RefaitRule::ALL {
+        let (b, v) = (base.house_edge(rule), variant.house_edge(rule));
+        out.push_str(&format!(
+            "House edge, refait {}: {:.4}% -> {:.4}% ({:+.4}%)\n",
+            rule,
+            b * 100.0,
+            v * 100.0,
+            (v - b) * 100.0
+        ));
+    }
+    out
+}
+
+/// The cards of `deck` left once the `removed` ones are dealt, for a row
+/// that already holds `start` points. The cards that scored those points
+/// are among the ones dealt, so they must score at least as many.
+fn mid_shoe(deck: &Deck, removed: &BTreeMap<u8, u16>, start: u16) -> Result<Deck> {
+    let left = deck.without(removed).map_err(|e| Error::Invalid(format!("--remove: {}", e)))?;
+    let mut counts = [0; RANKS];
+    for (&rank, &count) in removed {
+        counts[deck::rank_index(rank)] = count;
+    }
+    let dealt = Deck::from_counts(counts)
+        .with_values(deck.values())
+        .with_soft_aces(deck.soft_reach());
+    if u32::from(start) > dealt.max_points() {
+        return Err(Error::Invalid(format!(
+            "--start {}: the cards removed score at most {} points",
+            start,
+            dealt.max_points()
+        )));
+    }
+    info!("Solving from the {} cards left of {}", left.len(), deck.len());
+    Ok(left)
+}
+
+/// Explains that no row dealt from `deck` passes `threshold`, for the
+/// reports of a deck for which `rule::always_runs_out` holds.
+pub fn runs_out_message(deck: &Deck, threshold: u16) -> String {
+    let points = deck.max_points();
+    format!(
+        "The deck holds only {} points, so no row passes the threshold of {}: every \
+         row draws the whole deck and the averages describe that, not a finished row",
+        points, threshold
+    )
+}
+
+/// `exact`: prints the exact score and length distributions of one row.
+pub fn run(mut args: ExactArgs, color: bool) -> Result<()> {
+    // A variant stands in for the options it sets.
+    if let Some(variant) = args.variant.take() {
+        args.deck = variant.deck;
+        args.threshold = variant.threshold;
+        args.soft_aces = args.soft_aces.or(variant.soft_aces);
+    }
+    // Clap keeps --soft-aces from --aces-high, but not a variant's aces.
+    if args.aces_high && args.soft_aces.is_some() {
+        return Err(Error::Invalid(
+            "--aces-high conflicts with the soft aces of the variant".to_string(),
+        ));
+    }
+    let deck = match args.values {
+        Some(values) => args.deck.with_values(values),
+        None => args.deck,
+    };
+    let deck = if args.aces_high { deck.with_ace_value(ACE_HIGH) } else { deck };
+    let soft_reach = args
+        .soft_aces
+        .map(|bust| rule::soft_reach(bust, args.threshold.into()))
+        .transpose()
+        .map_err(|e| Error::Invalid(format!("--soft-aces: {}", e)))?;
+    let deck = deck
+        .with_jokers(args.jokers, args.joker_value)
+        .with_soft_aces(soft_reach)
+        .with_weights(args.weights.unwrap_or(deck::FAIR_WEIGHTS));
+    let deck = match &args.remove {
+        Some(removed) => mid_shoe(&deck, removed, args.start)?,
+        None => deck,
+    };
+    let start_time = Instant::now();
+    let threads = args.threads.unwrap_or_else(sim::default_threads);
+    let rule = SumThreshold(args.threshold.into());
+    if args.start == 0 && rule::always_runs_out(&rule, &deck) {
+        warn!("{}", runs_out_message(&deck, args.threshold.into()));
+    }
+    let dist = solve_from(&deck, &rule, args.start, args.solver, threads)?;
+    if args.check_mass {
+        check_mass(&dist)?;
+    }
+
+    let checks = if args.cross_check {
+        cross_check(&deck, &rule, args.start, &dist, threads)
+    } else {
+        Vec::new()
+    };
+    let shown = if args.normalize {
+        for (label, probs) in [("score", &dist.scores), ("length", &dist.lengths)] {
+            info!(
+                "The raw {} probabilities leave a residual of {:+.3e}",
+                label,
+                probs.values().sum::<f64>() - 1.0
+            );
+        }
+        dist.normalized()
+    } else {
+        dist
+    };
+    // With aces high, the same deck with aces low shows what the change did.
+    let aces_low = if args.aces_high {
+        let low = deck.clone().with_ace_value(1);
+        Some(solve_from(&low, &rule, args.start, args.solver, threads)?)
+    } else {
+        None
+    };
+    // Soft aces are set against aces fixed low, then fixed high.
+    let fixed_aces = if deck.soft_aces() {
+        let low = deck.clone().with_soft_aces(None);
+        let high = low.clone().with_ace_value(ACE_HIGH);
+        Some((
+            solve_from(&low, &rule, args.start, args.solver, threads)?,
+            solve_from(&high, &rule, args.start, args.solver, threads)?,
+        ))
+    } else {
+        None
+    };
+    // Likewise the deck without its jokers shows what they did.
+    let no_jokers = if deck.jokers() > 0 {
+        let plain = deck.clone().with_jokers(0, args.joker_value);
+        Some(solve_from(&plain, &rule, args.start, args.solver, threads)?)
+    } else {
+        None
+    };
+    // And the deck drawn fairly shows what its weights did.
+    let fair = if deck.is_weighted() {
+        let fair = deck.clone().with_weights(deck::FAIR_WEIGHTS);
+        Some(solve_from(&fair, &rule, args.start, args.solver, threads)?)
+    } else {
+        None
+    };
+    let report = |color| {
+        let mut report = format_report(&shown, color);
+        if let Some(low) = &aces_low {
+            report.push('\n');
+            report.push_str(&format_shift(
+                "Aces High against Aces Low",
+                ("Aces low", "Aces high"),
+                low,
+                &shown,
+                color,
+            ));
+        }
+        if let Some((low, high)) = &fixed_aces {
+            for (title, label, fixed) in [
+                ("Soft Aces against Aces Low", "Aces low", low),
+                ("Soft Aces against Aces High", "Aces high", high),
+            ] {
+                report.push('\n');
+                report.push_str(&format_shift(title, (label, "Soft aces"), fixed, &shown, color));
+            }
+        }
+        if let Some(plain) = &no_jokers {
+            report.push('\n');
+            report.push_str(&format_shift(
+                "Jokers against No Jokers",
+                ("No jokers", "Jokers"),
+                plain,
+                &shown,
+                color,
+            ));
+        }
+        if let Some(fair) = &fair {
+            report.push('\n');
+            report.push_str(&format_shift(
+                "Weighted against Fair Draw",
+                ("Fair draw", "Weighted"),
+                fair,
+                &shown,
+                color,
+            ));
+            // A coup's two rows both start from nothing.
+            if args.start == 0 {
+                report.push_str(&format_edge_shift(fair, &shown, args.threshold.into()));
+            }
+        }
+        if args.cross_check {
+            report.push('\n');
+            report.push_str(&format_cross_check(&checks, color));
+        }
+        report
+    };
+
+    print!("{}", report(color));
+    if let Some(path) = &args.output {
+        fs::write(path, report(false))
+            .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+        info!("Report saved to '{}'", path);
+    }
+
+    info!(
+        "Calculation finished in {:?} ({} memo states)",
+        start_time.elapsed(),
+        shown.states
+    );
+    let disagree: Vec<&str> = checks
+        .iter()
+        .filter(|check| !check.agrees())
+        .map(|check| check.name.as_str())
+        .collect();
+    if !disagree.is_empty() {
+        return Err(Error::Invalid(format!(
+            "Cross-check failed: {} differ from the {} solver by more than {:e}",
+            disagree.join(", "),
+            args.solver.to_possible_value().expect("no skipped variants").get_name(),
+            CROSS_CHECK_TOLERANCE
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> Deck {
+        Deck::preset(name).expect("built-in preset")
+    }
+
+    fn solve_at(deck: &Deck, threshold: u16, solver: Solver) -> ExactDist {
+        solve(deck, &SumThreshold(threshold), solver, 4).expect("the deck solves")
+    }
+
+    #[test]
+    fn every_solver_agrees_on_the_forty_card_decks() {
+        for name in ["standard-40", "spanish-40"] {
+            let deck = preset(name);
+            for threshold in [10, 21, 30] {
+                let rule = SumThreshold(threshold);
+                let dist = solve_at(&deck, threshold, Solver::Memo);
+                for check in cross_check(&deck, &rule, 0, &dist, 4) {
+                    assert!(check.result.is_ok(), "{} skipped {} at {}", check.name, name, threshold);
+                    assert!(check.agrees(), "{} differs on {} at {}", check.name, name, threshold);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_takes_only_decks_of_up_to_fifteen_a_rank() {
+        for name in ["standard-40", "spanish-40", "thirteen-52", "dice-36", "primes-24"] {
+            assert!(packed::distributions(&preset(name), &SumThreshold::default()).is_ok(), "{}", name);
+        }
+        for name in ["standard-52", "six-deck", "eight-deck"] {
+            let err = packed::distributions(&preset(name), &SumThreshold::default())
+                .err()
+                .unwrap_or_else(|| panic!("packed solved {}", name));
+            assert!(err.contains("at most 15 cards per value"), "{}: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn sharded_matches_serial_at_any_thread_count() {
+        let deck = Deck::from_standard_decks(2).expect("two decks fit");
+        let rule = SumThreshold::default();
+        let serial = solve_at(&deck, rule.0, Solver::Shoe);
+        for threads in [1, 2, 3, 8] {
+            let sharded = solve(&deck, &rule, Solver::Sharded, threads).expect("the deck solves");
+            assert!(largest_difference(&serial.scores, &sharded.scores) <= CROSS_CHECK_TOLERANCE);
+            assert!(largest_difference(&serial.lengths, &sharded.lengths) <= CROSS_CHECK_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn every_threshold_scores_just_past_itself() {
+        for name in ["dice-36", "primes-24"] {
+            let deck = preset(name);
+            let highest = deck.max_value() as u16;
+            for threshold in [21, 30, 40] {
+                let rule = SumThreshold(threshold);
+                let dist = solve_at(&deck, threshold, Solver::Memo);
+                check_mass(&dist).expect("every row ends");
+                let support = threshold + 1..=threshold + highest;
+                for score in dist.scores.keys() {
+                    assert!(support.contains(score), "{} scores {} at {}", name, score, threshold);
+                }
+                for check in cross_check(&deck, &rule, 0, &dist, 4) {
+                    assert!(check.agrees(), "{} differs on {} at {}", check.name, name, threshold);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_row_that_empties_the_deck_ends_on_its_total() {
+        let deck = Deck::from_ranks(&[2]).expect("two aces fit");
+        for solver in CHECKED_SOLVERS {
+            let dist = solve_at(&deck, 30, solver);
+            assert_eq!(dist.scores, BTreeMap::from([(2, 1.0)]));
+            assert_eq!(dist.lengths, BTreeMap::from([(2, 1.0)]));
+        }
+    }
+
+    // The residuals show the last bits of each sum, which differ in `f32`.
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn the_report_matches_its_golden_file() {
+        let dist = solve_at(&preset("standard-40"), 30, Solver::Memo);
+        assert_eq!(format_report(&dist, false), include_str!("testdata/exact_standard_40.txt"));
+    }
+
+    #[test]
+    fn an_all_ace_deck_averages_the_whole_deck() {
+        let deck = Deck::from_ranks(&[8]).expect("eight aces fit");
+        let rule = SumThreshold(200);
+        assert!(rule::always_runs_out(&rule, &deck));
+        let dist = solve_at(&deck, 200, Solver::Auto);
+        assert_eq!(dist.mean_score(), Some(8.0));
+        assert_eq!(dist.mean_length(), Some(8.0));
+        assert!(!format_report(&dist, false).contains("NaN"));
+
+        let empty = ExactDist { scores: BTreeMap::new(), lengths: BTreeMap::new(), states: 0 };
+        assert_eq!(empty.mean_score(), None);
+        let report = format_report(&empty, false);
+        assert!(report.contains("no data") && !report.contains("NaN"), "{}", report);
+    }
+
+    #[test]
+    fn the_last_card_left_can_end_a_row() {
+        // Each row draws down to a single card, the divisor at its smallest.
+        let tens = |n| {
+            let mut counts = [0; RANKS];
+            counts[deck::TENS] = n;
+            Deck::from_counts(counts)
+        };
+        for (cards, threshold) in [(1, 5), (3, 25)] {
+            let deck = tens(cards);
+            for solver in CHECKED_SOLVERS {
+                let dist = solve_at(&deck, threshold, solver);
+                assert_eq!(dist.scores, BTreeMap::from([(10 * cards, 1.0)]));
+                assert_eq!(dist.lengths, BTreeMap::from([(cards, 1.0)]));
+            }
+        }
+    }
+
+    #[test]
+    fn a_row_started_on_28_scores_31_to_38() {
+        // Ranks 1 to 8, so a row past 30 can score at most 38.
+        let deck = Deck::from_ranks(&[4; 8]).expect("eight ranks fit");
+        let rule = SumThreshold(30);
+        let dist = solve_from(&deck, &rule, 28, Solver::Memo, 4).expect("the deck solves");
+        check_mass(&dist).expect("every row ends");
+        assert!(dist.scores.keys().copied().eq(31..=38));
+        for check in cross_check(&deck, &rule, 28, &dist, 4) {
+            assert!(check.agrees(), "{} differs from a start of 28", check.name);
+        }
+    }
+
+    #[test]
+    fn a_row_started_past_the_threshold_draws_nothing() {
+        let deck = preset("standard-40");
+        for solver in CHECKED_SOLVERS {
+            let dist = solve_from(&deck, &SumThreshold(30), 33, solver, 4).expect("the deck solves");
+            assert_eq!(dist.scores, BTreeMap::from([(33, 1.0)]));
+            assert_eq!(dist.lengths, BTreeMap::from([(0, 1.0)]));
+        }
+    }
+}
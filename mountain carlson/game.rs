@@ -0,0 +1,119 @@
+use crate::deck::{JokerValue, PointWeights, JOKER};
+use crate::rule::{SoftAces, StoppingRule};
+use rand::{Rng, RngCore};
+
+/// Plays one full game and returns the outcome. Cards are drawn until `rule`
+/// stops the row or the deck runs out; a `JOKER` in `deck` scores as `joker`
+/// says, and the aces count as `soft` says if given. With `weights`, each
+/// card is drawn in proportion to the weight of its points rather than
+/// uniformly. Returns a tuple of (final_score, game_length).
+///
+/// Only the cards actually drawn are shuffled: a partial Fisher–Yates swaps
+/// a uniformly chosen card from the rest of the deck into each position as
+/// it is dealt. Most games end within a handful of cards, so this touches a
+/// fraction of a large shoe. `deck` is left permuted, which is fine for the
+/// next game since every draw picks uniformly from the cards not yet dealt.
+pub fn play_game<R: Rng>(
+    deck: &mut [u8],
+    rule: &dyn StoppingRule,
+    joker: JokerValue,
+    soft: Option<SoftAces>,
+    weights: Option<&PointWeights>,
+    rng: &mut R,
+) -> (u8, u8) {
+    let mut sum = 0;
+    let mut cards_drawn = 0;
+    // Whether a soft ace in the row counts high.
+    let mut high = false;
+
+    for i in 0..deck.len() {
+        let j = match weights {
+            Some(weights) => i + pick_weighted(&deck[i..], weights, rng),
+            None => rng.gen_range(i..deck.len()),
+        };
+        deck.swap(i, j);
+        let card = match deck[i] {
+            JOKER => joker.points(sum as u16, rule),
+            points => points,
+        };
+        match soft {
+            Some(soft) => {
+                let total;
+                (total, high) = soft.draw(sum as u16, high, card);
+                sum = total as u8;
+            }
+            None => sum += card,
+        }
+        cards_drawn += 1;
+        if rule.should_stop(sum as u16, cards_drawn as u16, card) {
+            break;
+        }
+    }
+    (sum, cards_drawn)
+}
+
+/// Index of a card of `cards` drawn with chance in proportion to the
+/// weight of its points. This walks every card left, so a weighted game
+/// costs far more than a fair one.
+fn pick_weighted<R: Rng>(cards: &[u8], weights: &PointWeights, rng: &mut R) -> usize {
+    let total: f64 = cards.iter().map(|&card| weights[card as usize]).sum();
+    let mut target = rng.gen::<f64>() * total;
+    for (i, &card) in cards.iter().enumerate() {
+        target -= weights[card as usize];
+        if target < 0.0 {
+            return i;
+        }
+    }
+    // Rounding may leave a sliver of the total past the last card.
+    cards.len() - 1
+}
+
+/// Wraps a generator to hand out its output from a buffer refilled `batch`
+/// words at a time, so the per-draw cost is a load and a bounds check
+/// rather than a call into the generator. The buffer is filled in the
+/// order the generator would have produced the words, so as long as only
+/// `u64`s are drawn, as `gen_range` over `usize` does on 64-bit targets,
+/// the games played do not depend on the batch size.
+pub struct BatchRng<R> {
+    inner: R,
+    buffer: Box<[u64]>,
+    next: usize,
+}
+
+impl<R: RngCore> BatchRng<R> {
+    /// Buffers `batch` words of `inner` at a time; `batch` must not be 0.
+    pub fn new(inner: R, batch: usize) -> Self {
+        assert!(batch > 0, "the RNG batch must hold at least one word");
+        let buffer = vec![0; batch].into_boxed_slice();
+        BatchRng { inner, next: buffer.len(), buffer }
+    }
+}
+
+impl<R: RngCore> RngCore for BatchRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        if self.next == self.buffer.len() {
+            self.inner.fill(&mut self.buffer[..]);
+            self.next = 0;
+        }
+        let word = self.buffer[self.next];
+        self.next += 1;
+        word
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use crate::table::Align;
+
+/// Escapes the characters LaTeX treats specially in text mode.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders a booktabs `tabular` with the cells padded so the `&` separators
+/// line up in the source. Headers and cells are escaped. With a caption the
+/// tabular is wrapped in a centered `table` float.
+pub fn tabular(columns: &[(&str, Align)], rows: &[Vec<String>], caption: Option<&str>) -> String {
+    let header: Vec<String> = columns.iter().map(|(h, _)| escape(h)).collect();
+    let body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| escape(c)).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &body {
+        for (i, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| {
+                let cell = cells.get(i).map(String::as_str).unwrap_or("");
+                match columns[i].1 {
+                    Align::Left => format!("{:<w$}", cell),
+                    Align::Right => format!("{:>w$}", cell),
+                }
+            })
+            .collect();
+        format!("{} \\\\\n", padded.join(" & "))
+    };
+
+    let spec: String = columns
+        .iter()
+        .map(|(_, a)| match a {
+            Align::Left => 'l',
+            Align::Right => 'r',
+        })
+        .collect();
+
+    let mut out = String::new();
+    if caption.is_some() {
+        out.push_str("\\begin{table}[ht]\n\\centering\n");
+    }
+    out.push_str(&format!("\\begin{{tabular}}{{{}}}\n\\toprule\n", spec));
+    out.push_str(&line(&header));
+    out.push_str("\\midrule\n");
+    for row in &body {
+        out.push_str(&line(row));
+    }
+    out.push_str("\\bottomrule\n\\end{tabular}\n");
+    if let Some(caption) = caption {
+        out.push_str(&format!("\\caption{{{}}}\n\\end{{table}}\n", escape(caption)));
+    }
+    out
+}
@@ -0,0 +1,21 @@
+//! Code shared by the Monte Carlo simulator and the exact solvers.
+
+pub mod bet;
+pub mod color;
+pub mod deck;
+pub mod dist;
+pub mod game;
+pub mod latex;
+pub mod npy;
+pub mod rule;
+pub mod shoe;
+pub mod table;
+pub mod variant;
+
+// The exact solvers keep their historical files next to this folder.
+#[path = "../memo_sim.rs"]
+pub mod memo_sim;
+#[path = "../30_b_w.rs"]
+pub mod packed;
+#[path = "../512.rs"]
+pub mod shoe_solver;
@@ -0,0 +1,24 @@
+use std::io::{self, Write};
+
+/// Writes `values` as a one-dimensional NumPy `.npy` array of little-endian
+/// `f64` (format version 1.0), loadable with `numpy.load`.
+pub fn write_f64_array<W: Write>(mut writer: W, values: &[f64]) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}",
+        values.len()
+    );
+    // Magic (6) + version (2) + header length (2) + header must be a multiple
+    // of 64 bytes, with the header ending in a newline.
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY")?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for v in values {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,251 @@
+use crate::compare;
+use crate::error::{Error, Result};
+use crate::exact::{self, Solver};
+use crate::sim::{self, SimResults};
+use clap::Args;
+use log::{error, info};
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, Deck};
+use monte_carlo_sim::rule::{SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Options of the `repl` subcommand.
+#[derive(Args)]
+pub struct ReplArgs {
+    /// Seed the seeds of successive runs are drawn from; random when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+const HELP: &str = "\
+Commands:
+  deck [NAME|FILE]        show or set the deck (clears the results)
+  values [RANK=POINTS,..] show or set the points per rank (clears the results)
+  threshold [N]           show or set the threshold (clears the results)
+  threads [N]             show or set the worker threads
+  seed N                  restart the run seeds from N
+  run [GAMES]             play GAMES more games, 1e6 by default
+  show [report|scores|lengths|summary]
+                          print the accumulated results
+  compare exact           compare the results with the exact solution
+  reset                   clear the results
+  help                    show this list
+  quit                    leave the REPL";
+
+/// Whether the loop goes on after a command.
+enum Step {
+    Continue,
+    Quit,
+}
+
+/// The deck, rules and accumulated results kept between commands.
+struct Session {
+    deck: Deck,
+    threshold: u8,
+    threads: u64,
+    /// Draws one seed per `run`, so a session replays from its seed.
+    seeds: StdRng,
+    results: SimResults,
+    color: bool,
+}
+
+impl Session {
+    fn new(seed: u64, color: bool) -> Self {
+        let deck = Deck::preset("spanish-40").expect("built-in preset");
+        let results = SimResults::new(&deck, &SumThreshold(DEFAULT_THRESHOLD.into()));
+        Session {
+            deck,
+            threshold: DEFAULT_THRESHOLD,
+            threads: sim::default_threads(),
+            seeds: StdRng::seed_from_u64(seed),
+            results,
+            color,
+        }
+    }
+
+    fn rule(&self) -> SumThreshold {
+        SumThreshold(self.threshold.into())
+    }
+
+    /// Drops the accumulated games, which no longer match the settings.
+    fn clear(&mut self) {
+        if self.results.total_games > 0 {
+            info!("Cleared {} games.", self.results.total_games);
+        }
+        self.results = SimResults::new(&self.deck, &self.rule());
+    }
+
+    fn execute(&mut self, command: &str, arg: Option<&str>) -> Result<Step> {
+        match (command, arg) {
+            ("help", _) => println!("{}", HELP),
+            ("quit" | "exit", _) => return Ok(Step::Quit),
+            ("deck", None) => println!("{} ({} cards)", self.deck, self.deck.len()),
+            ("deck", Some(arg)) => {
+                // Preset names may also be typed with underscores.
+                let deck = deck::parse_arg(arg)
+                    .or_else(|e| deck::parse_arg(&arg.replace('_', "-")).map_err(|_| e))
+                    .map_err(Error::Invalid)?;
+                if deck.is_empty() {
+                    return Err(Error::Invalid("The deck has no cards".to_string()));
+                }
+                self.deck = deck.with_values(self.deck.values());
+                self.clear();
+            }
+            ("values", None) => {
+                let ranks = self.deck.ranks();
+                let values: Vec<String> =
+                    self.deck.values()[..ranks].iter().map(u8::to_string).collect();
+                println!("{}", values.join(","));
+            }
+            ("values", Some(arg)) => {
+                let values = deck::parse_values(arg).map_err(Error::Invalid)?;
+                self.deck = self.deck.clone().with_values(values);
+                self.clear();
+            }
+            ("threshold", None) => println!("{}", self.threshold),
+            ("threshold", Some(arg)) => {
+                self.threshold = arg
+                    .parse()
+                    .ok()
+                    .filter(|&t: &u8| THRESHOLD_RANGE.contains(&(t as i64)))
+                    .ok_or_else(|| {
+                        Error::Invalid(format!(
+                            "threshold must be in {}..={}",
+                            THRESHOLD_RANGE.start(),
+                            THRESHOLD_RANGE.end()
+                        ))
+                    })?;
+                self.clear();
+            }
+            ("threads", None) => println!("{}", self.threads),
+            ("threads", Some(arg)) => {
+                self.threads = arg
+                    .parse()
+                    .ok()
+                    .filter(|t| (1..=1024).contains(t))
+                    .ok_or_else(|| Error::Invalid("threads must be in 1..=1024".to_string()))?;
+            }
+            ("seed", Some(arg)) => {
+                let seed = arg
+                    .parse()
+                    .map_err(|_| Error::Invalid(format!("'{}' is not a seed", arg)))?;
+                self.seeds = StdRng::seed_from_u64(seed);
+            }
+            ("run", arg) => self.run(parse_games(arg.unwrap_or("1e6"))?)?,
+            ("show", arg) => self.show(arg.unwrap_or("report"))?,
+            ("compare", Some("exact")) => {
+                self.require_games()?;
+                let dist = exact::solve(&self.deck, &self.rule(), Solver::Auto, self.threads)?;
+                compare::print_comparison(&dist, &self.results, self.color);
+            }
+            ("reset", None) => self.clear(),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "Unknown command '{}'; type 'help' for the list",
+                    [Some(command), arg].into_iter().flatten().collect::<Vec<_>>().join(" ")
+                )))
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    /// Plays `games` more games and adds them to the session results.
+    fn run(&mut self, games: u64) -> Result<()> {
+        let rule = self.rule();
+        sim::check_game_width(&self.deck, &rule)?;
+        let seed = self.seeds.gen();
+        let batch = sim::simulate(&self.deck, &rule, games, seed, self.threads);
+        self.results
+            .merge(&batch)
+            .expect("the batch was played with the session deck");
+        info!("Played {} games, {} in total.", games, self.results.total_games);
+        Ok(())
+    }
+
+    fn show(&self, what: &str) -> Result<()> {
+        self.require_games()?;
+        let (label, counts) = match what {
+            "report" => {
+                println!("{}", sim::format_report(&self.results, self.color));
+                return Ok(());
+            }
+            "summary" => {
+                sim::print_summary_line(&self.results);
+                return Ok(());
+            }
+            "scores" => ("Score", &self.results.score_counts),
+            "lengths" => ("Length", &self.results.length_counts),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "Cannot show '{}'; expected report, scores, lengths or summary",
+                    what
+                )))
+            }
+        };
+        let table = sim::distribution_table(label, counts, self.results.total_games);
+        println!("{}", color::bold(&format!("--- {} Distribution ---", label), self.color));
+        print!("{}", table.render_styled(self.color));
+        Ok(())
+    }
+
+    fn require_games(&self) -> Result<()> {
+        if self.results.total_games == 0 {
+            return Err(Error::Invalid("No games played yet; try 'run'".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses a game count, accepting scientific notation such as `1e7`.
+fn parse_games(arg: &str) -> Result<u64> {
+    arg.parse::<u64>()
+        .ok()
+        .or_else(|| {
+            let games: f64 = arg.parse().ok()?;
+            let whole = games.is_finite() && games.fract() == 0.0 && games <= u64::MAX as f64;
+            whole.then_some(games as u64)
+        })
+        .filter(|&games| games > 0)
+        .ok_or_else(|| Error::Invalid(format!("'{}' is not a positive number of games", arg)))
+}
+
+/// `repl`: reads commands from stdin, one per line, until `quit` or end of
+/// input. Errors are reported and the session carries on.
+pub fn run(args: ReplArgs, color: bool) -> Result<()> {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        info!("Using random seed {} (pass --seed {} to reproduce this session).", seed, seed);
+    }
+    let mut session = Session::new(seed, color);
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut lines = stdin.lock().lines();
+    loop {
+        // The prompt goes to stderr with the other status output, and only
+        // when a person is typing.
+        if interactive {
+            eprint!("trente> ");
+            let _ = io::stderr().flush();
+        }
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| Error::Io(format!("Error reading stdin: {}", e)))?,
+            None => return Ok(()),
+        };
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let arg = words.next();
+        if words.next().is_some() {
+            error!("'{}' takes at most one argument", command);
+            continue;
+        }
+        match session.execute(command, arg) {
+            Ok(Step::Quit) => return Ok(()),
+            Ok(Step::Continue) => {}
+            Err(e) => error!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,787 @@
+use crate::coup;
+use crate::error::{Error, Result};
+use clap::Args;
+use log::info;
+use monte_carlo_sim::bet::{self, Bet, Payouts, RefaitRule, Settlement};
+use monte_carlo_sim::color;
+use monte_carlo_sim::deck::{self, Deck, ACE_HIGH, RANKS};
+use monte_carlo_sim::rule::{DEFAULT_THRESHOLD, THRESHOLD_RANGE};
+use monte_carlo_sim::shoe::{Outcome, Shoe};
+use monte_carlo_sim::table::{argmax, Align, Table};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+/// Options of the `ruin` subcommand.
+#[derive(Args)]
+pub struct RuinArgs {
+    /// Bankroll each session starts with.
+    #[arg(long, default_value_t = 100.0)]
+    bankroll: f64,
+
+    /// Stake on every coup, or the base stake of a progression.
+    #[arg(long, default_value_t = 1.0)]
+    bet: f64,
+
+    /// How the stake changes from coup to coup.
+    #[arg(long, value_enum, default_value = "flat")]
+    strategy: Strategy,
+
+    /// Largest stake the table accepts; a Martingale stops doubling there.
+    /// No limit when omitted.
+    #[arg(long)]
+    table_max: Option<f64>,
+
+    /// Coups a session lasts if the player is not ruined first.
+    #[arg(long, default_value_t = 1_000)]
+    coups: u64,
+
+    /// Number of sessions to play.
+    #[arg(long, default_value_t = 10_000)]
+    sessions: u64,
+
+    /// End a session early once it is this much ahead.
+    #[arg(long, value_name = "PROFIT")]
+    target: Option<f64>,
+
+    /// What each bet pays on a win, as `bet=multiple` pairs such as
+    /// `couleur=0.95`; bets not listed pay even money.
+    #[arg(long, value_parser = bet::parse_payouts, default_value = "", hide_default_value = true)]
+    payouts: Payouts,
+
+    /// The bet placed on every coup.
+    #[arg(long, value_enum, default_value = "noir")]
+    bet_on: Bet,
+
+    /// How a refait settles the bet: half, prison, or commission with an
+    /// optional percentage such as `commission=2.5`.
+    #[arg(long, value_name = "RULE", default_value = "half", value_parser = bet::parse_refait)]
+    refait: RefaitRule,
+
+    /// Stop drawing each row once its total exceeds this value.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_THRESHOLD,
+        value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE),
+    )]
+    threshold: u8,
+
+    /// Shoe to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin.
+    #[arg(long, default_value = "six-deck", value_parser = deck::parse_arg)]
+    deck: Deck,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Seed for the random number generator; random when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write a CSV ledger of every coup of every session to this file: the
+    /// bet, the winner, the payoff and the running balance. Long runs make
+    /// large files.
+    #[arg(long, value_name = "FILE")]
+    ledger: Option<String>,
+
+    /// Play every strategy on the same coups instead, and compare their
+    /// results in one table.
+    #[arg(long, conflicts_with_all = ["strategy", "ledger"])]
+    compare_strategies: bool,
+
+    /// Search flat bets from --bet up to the bankroll instead, and report
+    /// the one most likely to reach --target before ruin.
+    #[arg(
+        long,
+        requires = "target",
+        conflicts_with_all = ["strategy", "ledger", "compare_strategies"]
+    )]
+    optimize_bet: bool,
+
+    /// Also save the report to this file.
+    #[arg(long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// Number of rows in the survival time table.
+const SURVIVAL_BUCKETS: u64 = 10;
+
+/// Number of rows in the net result histogram.
+const HISTOGRAM_BINS: usize = 20;
+
+/// Number of flat bets `--optimize-bet` tries.
+const BET_CANDIDATES: usize = 16;
+
+/// How the stake changes from coup to coup.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Strategy {
+    /// The same stake on every coup.
+    Flat,
+    /// Double the stake after every loss, up to the table maximum, and go
+    /// back to the base stake after a win.
+    Martingale,
+    /// Raise the stake by the base stake after every loss, up to the table
+    /// maximum, and lower it by as much after a win.
+    #[value(name = "dalembert")]
+    DAlembert,
+}
+
+impl Strategy {
+    /// Every strategy, in report order.
+    pub const ALL: [Strategy; 3] = [Strategy::Flat, Strategy::Martingale, Strategy::DAlembert];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Flat => "flat",
+            Strategy::Martingale => "Martingale",
+            Strategy::DAlembert => "d'Alembert",
+        }
+    }
+
+    /// The stake after a coup settled at `stake`, given whether it was won.
+    /// Ties and imprisoned stakes leave the stake as it is.
+    fn next_stake(self, stake: f64, won: bool, session: &Session) -> f64 {
+        match self {
+            Strategy::Flat => session.bet,
+            Strategy::Martingale if won => session.bet,
+            Strategy::Martingale => (stake * 2.0).min(session.table_max),
+            Strategy::DAlembert if won => (stake - session.bet).max(session.bet),
+            Strategy::DAlembert => (stake + session.bet).min(session.table_max),
+        }
+    }
+}
+
+/// The stakes and length of a session.
+struct Session {
+    bankroll: f64,
+    bet: f64,
+    strategy: Strategy,
+    table_max: f64,
+    coups: u64,
+    /// The profit that ends a session early; infinite without `--target`.
+    target: f64,
+    bet_on: Bet,
+    /// What a win pays, as a multiple of the stake.
+    payout: f64,
+    refait: RefaitRule,
+    threshold: u8,
+}
+
+impl Session {
+    /// The bet and what it pays, for report headers.
+    fn bet_label(&self) -> String {
+        format!("{} at {}:1", self.bet_on.name(), self.payout)
+    }
+}
+
+/// How one session ended.
+struct SessionEnd {
+    /// The coup on which the player could no longer cover the base stake,
+    /// or `None` if the session ran its course.
+    ruined_on: Option<u64>,
+    /// Whether the session ended on reaching the target.
+    reached: bool,
+    bankroll: f64,
+    /// The sum of every stake placed.
+    staked: f64,
+}
+
+/// Plays one session from a freshly shuffled shoe, reshuffling whenever it
+/// runs out. A stake larger than the bankroll is cut down to what is left.
+fn play_session(
+    session: &Session,
+    shoe: &mut Shoe,
+    rng: &mut StdRng,
+    mut ledger: Option<&mut Ledger>,
+) -> io::Result<SessionEnd> {
+    shoe.shuffle(rng);
+    let mut bankroll = session.bankroll;
+    let mut staked = 0.0;
+    let mut next_stake = session.bet;
+    // The stake of the last bet placed.
+    let mut stake = next_stake;
+    // Whether that stake is held en prison, already taken from the bankroll.
+    let mut imprisoned = false;
+    for coup_number in 1..=session.coups {
+        let coup = loop {
+            match shoe.deal_coup(session.threshold) {
+                Some(coup) => break coup,
+                None => shoe.shuffle(rng),
+            }
+        };
+        let outcome = coup.outcome(session.threshold);
+        let settlement = session.bet_on.settle(outcome, coup.first_card_black);
+        let before = bankroll;
+        // Whether the bet was settled for good, and if so whether it won,
+        // with the ledger's name for what became of it.
+        let (settled, result) = if imprisoned {
+            match settlement {
+                Settlement::Win => {
+                    bankroll += stake;
+                    imprisoned = false;
+                    (Some(true), "freed")
+                }
+                Settlement::Push => (None, "held"),
+                Settlement::Lose | Settlement::Refait => {
+                    imprisoned = false;
+                    (Some(false), "forfeited")
+                }
+            }
+        } else {
+            stake = next_stake.min(bankroll);
+            staked += stake;
+            match settlement {
+                Settlement::Win => {
+                    bankroll += stake * session.payout;
+                    (Some(true), "win")
+                }
+                Settlement::Lose => {
+                    bankroll -= stake;
+                    (Some(false), "lose")
+                }
+                Settlement::Push => (None, "push"),
+                Settlement::Refait => match session.refait {
+                    RefaitRule::Half => {
+                        bankroll -= stake / 2.0;
+                        (Some(false), "half lost")
+                    }
+                    RefaitRule::Commission(rate) => {
+                        bankroll -= stake * rate;
+                        (Some(false), "commission")
+                    }
+                    RefaitRule::Prison => {
+                        bankroll -= stake;
+                        imprisoned = true;
+                        (None, "imprisoned")
+                    }
+                },
+            }
+        };
+        if let Some(ledger) = ledger.as_deref_mut() {
+            ledger.record(coup_number, stake, outcome, result, bankroll - before, bankroll)?;
+        }
+        if let Some(won) = settled {
+            next_stake = session.strategy.next_stake(stake, won, session);
+        }
+        if !imprisoned && bankroll < session.bet {
+            return Ok(SessionEnd {
+                ruined_on: Some(coup_number),
+                reached: false,
+                bankroll,
+                staked,
+            });
+        }
+        if !imprisoned && bankroll >= session.bankroll + session.target {
+            return Ok(SessionEnd {
+                ruined_on: None,
+                reached: true,
+                bankroll,
+                staked,
+            });
+        }
+    }
+    // A stake still imprisoned when the session ends is returned.
+    if imprisoned {
+        bankroll += stake;
+    }
+    Ok(SessionEnd {
+        ruined_on: None,
+        reached: false,
+        bankroll,
+        staked,
+    })
+}
+
+/// The `--ledger` file: a CSV line per coup of every session.
+struct Ledger {
+    out: BufWriter<File>,
+    bet_on: Bet,
+    /// The session being played, from 1.
+    session: u64,
+}
+
+impl Ledger {
+    fn create(path: &str, bet_on: Bet) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "session,coup,bet,stake,winner,result,payoff,balance")?;
+        Ok(Ledger {
+            out,
+            bet_on,
+            session: 0,
+        })
+    }
+
+    fn record(
+        &mut self,
+        coup: u64,
+        stake: f64,
+        winner: Outcome,
+        result: &str,
+        payoff: f64,
+        balance: f64,
+    ) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{}",
+            self.session,
+            coup,
+            self.bet_on.name(),
+            stake,
+            winner.name(),
+            result,
+            payoff,
+            balance
+        )
+    }
+}
+
+/// The outcome of every session of a run.
+struct RuinResults {
+    sessions: u64,
+    /// The coup each ruined session ended on, in ascending order.
+    ruin_coups: Vec<u64>,
+    /// Sum of the final bankrolls of the sessions that survived.
+    survivor_bankroll: f64,
+    /// The final bankroll of every session, in ascending order.
+    final_bankrolls: Vec<f64>,
+    /// The sum of every stake placed in every session.
+    staked: f64,
+    /// Sessions that reached the target.
+    reached: u64,
+}
+
+impl RuinResults {
+    /// The mean and standard deviation of the net result of a session.
+    fn net_result(&self, bankroll: f64) -> (f64, f64) {
+        let n = self.final_bankrolls.len() as f64;
+        let mean = self.final_bankrolls.iter().sum::<f64>() / n - bankroll;
+        let variance = self
+            .final_bankrolls
+            .iter()
+            .map(|b| (b - bankroll - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        (mean, variance.sqrt())
+    }
+}
+
+/// Plays `sessions` sessions, each shuffled from its own seed drawn from
+/// `seed`, so every strategy played with the same seed sees the same coups
+/// until its session ends.
+fn play_sessions(
+    session: &Session,
+    deck: &Deck,
+    sessions: u64,
+    seed: u64,
+    mut ledger: Option<&mut Ledger>,
+) -> io::Result<RuinResults> {
+    let mut seeds = StdRng::seed_from_u64(seed);
+    let mut shoe = Shoe::new(deck);
+    let mut results = RuinResults {
+        sessions,
+        ruin_coups: Vec::new(),
+        survivor_bankroll: 0.0,
+        final_bankrolls: Vec::with_capacity(sessions as usize),
+        staked: 0.0,
+        reached: 0,
+    };
+    for _ in 0..sessions {
+        if let Some(ledger) = ledger.as_deref_mut() {
+            ledger.session += 1;
+        }
+        let mut rng = StdRng::seed_from_u64(seeds.gen());
+        let end = play_session(session, &mut shoe, &mut rng, ledger.as_deref_mut())?;
+        match end.ruined_on {
+            Some(coup) => results.ruin_coups.push(coup),
+            None => results.survivor_bankroll += end.bankroll,
+        }
+        results.final_bankrolls.push(end.bankroll);
+        results.staked += end.staked;
+        results.reached += end.reached as u64;
+    }
+    results.ruin_coups.sort_unstable();
+    results.final_bankrolls.sort_unstable_by(f64::total_cmp);
+    if let Some(ledger) = ledger {
+        ledger.out.flush()?;
+    }
+    Ok(results)
+}
+
+/// Quantiles of the net result of a session, over every session played.
+fn format_final_bankrolls(session: &Session, results: &RuinResults, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Session Results ---", color));
+    out.push('\n');
+    let finals = &results.final_bankrolls;
+    let (mean, std_dev) = results.net_result(session.bankroll);
+    out.push_str(&format!("Mean net result: {:+.2} (std dev {:.2})\n", mean, std_dev));
+    let mut table = Table::new(&[("Quantile", Align::Left), ("Net result", Align::Right)]);
+    for (label, q) in [
+        ("min", 0.0),
+        ("5%", 0.05),
+        ("10%", 0.1),
+        ("25%", 0.25),
+        ("median", 0.5),
+        ("75%", 0.75),
+        ("90%", 0.9),
+        ("95%", 0.95),
+        ("max", 1.0),
+    ] {
+        let index = ((finals.len() - 1) as f64 * q).round() as usize;
+        table.add_row(vec![
+            label.to_string(),
+            format!("{:+.2}", finals[index] - session.bankroll),
+        ]);
+    }
+    out.push_str(&table.render());
+
+    out.push('\n');
+    out.push_str(&color::bold("--- Net Result Histogram ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Net result", Align::Left),
+        ("Sessions", Align::Right),
+        ("Share", Align::Right),
+    ]);
+    let (low, high) = (finals[0], finals[finals.len() - 1]);
+    let bins = if high > low { HISTOGRAM_BINS } else { 1 };
+    let width = (high - low) / bins as f64;
+    let mut counts = vec![0u64; bins];
+    for &bankroll in finals {
+        let bin = if width > 0.0 { ((bankroll - low) / width) as usize } else { 0 };
+        // The highest result falls on the upper edge of the last bin.
+        counts[bin.min(bins - 1)] += 1;
+    }
+    for (bin, &count) in counts.iter().enumerate() {
+        let from = low + width * bin as f64 - session.bankroll;
+        table.add_row(vec![
+            format!("{:+.2} to {:+.2}", from, from + width),
+            count.to_string(),
+            format!("{:.2}%", count as f64 / finals.len() as f64 * 100.0),
+        ]);
+    }
+    if let Some(mode) = argmax(counts.iter()) {
+        table.highlight_row(mode);
+    }
+    out.push_str(&table.render_styled(color));
+    out
+}
+
+/// Formats the ruin probability and the survival times of the ruined
+/// sessions.
+fn format_report(session: &Session, results: &RuinResults, color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Risk of Ruin ---", color));
+    out.push('\n');
+    let n = results.sessions as f64;
+    let ruined = results.ruin_coups.len() as u64;
+    let p = ruined as f64 / n;
+    out.push_str(&format!(
+        "Sessions: {} of up to {} coups, bankroll {}, {} on {} {}, refait {}\n",
+        results.sessions,
+        session.coups,
+        session.bankroll,
+        session.bet,
+        session.bet_label(),
+        match session.strategy {
+            Strategy::Flat => "a coup".to_string(),
+            strategy if session.table_max.is_finite() => {
+                format!("by {} up to {}", strategy.name(), session.table_max)
+            }
+            strategy => format!("by {}", strategy.name()),
+        },
+        session.refait
+    ));
+    out.push_str(&format!(
+        "Ruined: {} ({:.4}% ± {:.4}%)\n",
+        ruined,
+        p * 100.0,
+        (p * (1.0 - p) / n).sqrt() * 100.0
+    ));
+    if session.target.is_finite() {
+        let q = results.reached as f64 / n;
+        out.push_str(&format!(
+            "Reached the target of {:+}: {} ({:.4}% ± {:.4}%)\n",
+            session.target,
+            results.reached,
+            q * 100.0,
+            (q * (1.0 - q) / n).sqrt() * 100.0
+        ));
+    }
+    let survivors = results.sessions - ruined;
+    if survivors > 0 {
+        out.push_str(&format!(
+            "Mean final bankroll of survivors: {:.2}\n",
+            results.survivor_bankroll / survivors as f64
+        ));
+    }
+    let coups = &results.ruin_coups;
+    if ruined > 0 {
+        let mean = coups.iter().sum::<u64>() as f64 / ruined as f64;
+        out.push_str(&format!(
+            "Coups to ruin: mean {:.1}, median {}\n",
+            mean,
+            coups[coups.len() / 2]
+        ));
+    }
+    out.push('\n');
+    out.push_str(&format_final_bankrolls(session, results, color));
+    if ruined == 0 {
+        return out;
+    }
+
+    out.push('\n');
+    out.push_str(&color::bold("--- Survival Time ---", color));
+    out.push('\n');
+    let mut table = Table::new(&[
+        ("Ruined on coup", Align::Left),
+        ("Sessions", Align::Right),
+        ("Share", Align::Right),
+        ("Cumulative", Align::Right),
+    ]);
+    let width = session.coups.div_ceil(SURVIVAL_BUCKETS);
+    let mut cumulative = 0;
+    for start in (1..=session.coups).step_by(width as usize) {
+        let end = (start + width - 1).min(session.coups);
+        let count = coups.partition_point(|&c| c <= end) - coups.partition_point(|&c| c < start);
+        cumulative += count;
+        table.add_row(vec![
+            format!("{}-{}", start, end),
+            count.to_string(),
+            format!("{:.4}%", count as f64 / n * 100.0),
+            format!("{:.4}%", cumulative as f64 / n * 100.0),
+        ]);
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// `--compare-strategies`: every strategy's results side by side, each
+/// played on the same coups.
+fn format_comparison(session: &Session, results: &[(Strategy, RuinResults)], color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Strategy Comparison ---", color));
+    out.push('\n');
+    out.push_str(&format!(
+        "Sessions: {} of up to {} coups, bankroll {}, base stake {} on {}{}, refait {}\n",
+        session_count(results),
+        session.coups,
+        session.bankroll,
+        session.bet,
+        session.bet_label(),
+        if session.table_max.is_finite() {
+            format!(" up to {}", session.table_max)
+        } else {
+            String::new()
+        },
+        session.refait
+    ));
+    let mut table = Table::new(&[
+        ("Strategy", Align::Left),
+        ("Mean net", Align::Right),
+        ("Std dev", Align::Right),
+        ("Return per unit", Align::Right),
+        ("Ruined", Align::Right),
+    ]);
+    for (strategy, results) in results {
+        let (mean, std_dev) = results.net_result(session.bankroll);
+        let net: f64 = mean * results.sessions as f64;
+        let ruin = results.ruin_coups.len() as f64 / results.sessions as f64;
+        table.add_row(vec![
+            strategy.name().to_string(),
+            format!("{:+.2}", mean),
+            format!("{:.2}", std_dev),
+            format!("{:+.4}%", net / results.staked * 100.0),
+            format!("{:.4}%", ruin * 100.0),
+        ]);
+    }
+    out.push_str(&table.render());
+    out
+}
+
+/// `--optimize-bet`: `BET_CANDIDATES` flat bets spaced evenly on a log
+/// scale from `min` to `max`.
+fn bet_candidates(min: f64, max: f64) -> Vec<f64> {
+    if max <= min {
+        return vec![min];
+    }
+    let ratio = (max / min).powf(1.0 / (BET_CANDIDATES - 1) as f64);
+    let mut bets: Vec<f64> = (0..BET_CANDIDATES).map(|i| min * ratio.powi(i as i32)).collect();
+    // Exactly the bankroll, not a hair below: betting it all must double it.
+    bets[BET_CANDIDATES - 1] = max;
+    bets
+}
+
+/// `--optimize-bet`: how often each flat bet reached the target, was ruined
+/// or ran out of coups, and the bet most likely to reach the target.
+fn format_optimization(session: &Session, results: &[(f64, RuinResults)], color: bool) -> String {
+    let mut out = String::new();
+    out.push_str(&color::bold("--- Flat Bet Search ---", color));
+    out.push('\n');
+    out.push_str(&format!(
+        "Sessions: {} of up to {} coups, bankroll {}, target {:+}, on {}, refait {}\n",
+        results.first().map_or(0, |(_, results)| results.sessions),
+        session.coups,
+        session.bankroll,
+        session.target,
+        session.bet_label(),
+        session.refait
+    ));
+    let mut table = Table::new(&[
+        ("Bet", Align::Right),
+        ("Reached", Align::Right),
+        ("Ruined", Align::Right),
+        ("Neither", Align::Right),
+    ]);
+    let share = |count: u64, results: &RuinResults| count as f64 / results.sessions as f64;
+    for (bet, results) in results {
+        let ruined = results.ruin_coups.len() as u64;
+        let neither = results.sessions - results.reached - ruined;
+        table.add_row(vec![
+            format!("{:.2}", bet),
+            format!("{:.4}%", share(results.reached, results) * 100.0),
+            format!("{:.4}%", share(ruined, results) * 100.0),
+            format!("{:.4}%", share(neither, results) * 100.0),
+        ]);
+    }
+    let best = argmax(results.iter().map(|(_, results)| results.reached));
+    if let Some(best) = best {
+        table.highlight_row(best);
+    }
+    out.push_str(&table.render_styled(color));
+    if let Some(best) = best {
+        let (bet, results) = &results[best];
+        let p = share(results.reached, results);
+        out.push_str(&format!(
+            "Best flat bet: {:.2}, reaching the target {:.4}% ± {:.4}% of the time\n",
+            bet,
+            p * 100.0,
+            (p * (1.0 - p) / results.sessions as f64).sqrt() * 100.0
+        ));
+    }
+    out
+}
+
+fn session_count(results: &[(Strategy, RuinResults)]) -> u64 {
+    results.first().map_or(0, |(_, results)| results.sessions)
+}
+
+/// `ruin`: plays many sessions of a betting strategy and reports how often
+/// the bankroll runs out before the session ends.
+pub fn run(args: RuinArgs, color: bool) -> Result<()> {
+    let deck = match args.values {
+        Some(values) => args.deck.with_values(values),
+        None => args.deck,
+    };
+    let deck = if args.aces_high { deck.with_ace_value(ACE_HIGH) } else { deck };
+    let table_max = args.table_max.unwrap_or(f64::INFINITY);
+    for (name, amount) in [
+        ("--bankroll", args.bankroll),
+        ("--bet", args.bet),
+        ("--table-max", args.table_max.unwrap_or(args.bet)),
+        ("--target", args.target.unwrap_or(args.bet)),
+    ] {
+        if !(amount.is_finite() && amount > 0.0) {
+            return Err(Error::Invalid(format!("{} must be a positive amount", name)));
+        }
+    }
+    if args.bet > args.bankroll {
+        return Err(Error::Invalid("--bet must not exceed --bankroll".to_string()));
+    }
+    if table_max < args.bet {
+        return Err(Error::Invalid("--table-max must not be below --bet".to_string()));
+    }
+    if args.coups == 0 {
+        return Err(Error::Invalid("--coups must be at least 1".to_string()));
+    }
+    if args.sessions == 0 {
+        return Err(Error::Invalid("--sessions must be at least 1".to_string()));
+    }
+    coup::check_coup_deck(&deck, args.threshold)?;
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        info!("Using random seed {} (pass --seed {} to reproduce this run).", seed, seed);
+    }
+    let mut session = Session {
+        bankroll: args.bankroll,
+        bet: args.bet,
+        strategy: args.strategy,
+        table_max,
+        coups: args.coups,
+        target: args.target.unwrap_or(f64::INFINITY),
+        bet_on: args.bet_on,
+        payout: args.payouts.get(args.bet_on),
+        refait: args.refait,
+        threshold: args.threshold,
+    };
+    if args.optimize_bet {
+        let mut results = Vec::new();
+        for bet in bet_candidates(args.bet, args.bankroll.min(table_max)) {
+            session.bet = bet;
+            let played = play_sessions(&session, &deck, args.sessions, seed, None)
+                .expect("no ledger to write");
+            results.push((bet, played));
+        }
+        let report = |color| format_optimization(&session, &results, color);
+        print!("{}", report(color));
+        if let Some(path) = &args.output {
+            fs::write(path, report(false))
+                .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+            info!("Report saved to '{}'", path);
+        }
+        return Ok(());
+    }
+    if args.compare_strategies {
+        let mut results = Vec::new();
+        for strategy in Strategy::ALL {
+            session.strategy = strategy;
+            let played = play_sessions(&session, &deck, args.sessions, seed, None)
+                .expect("no ledger to write");
+            results.push((strategy, played));
+        }
+        let report = |color| format_comparison(&session, &results, color);
+        print!("{}", report(color));
+        if let Some(path) = &args.output {
+            fs::write(path, report(false))
+                .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+            info!("Report saved to '{}'", path);
+        }
+        return Ok(());
+    }
+
+    let mut ledger = match &args.ledger {
+        Some(path) => Some(
+            Ledger::create(path, args.bet_on)
+                .map_err(|e| Error::Io(format!("Error creating ledger '{}': {}", path, e)))?,
+        ),
+        None => None,
+    };
+    let results = play_sessions(&session, &deck, args.sessions, seed, ledger.as_mut())
+        .map_err(|e| {
+            Error::Io(format!(
+                "Error writing ledger '{}': {}",
+                args.ledger.as_deref().unwrap_or_default(),
+                e
+            ))
+        })?;
+    if let Some(path) = &args.ledger {
+        info!("Ledger saved to '{}'", path);
+    }
+
+    print!("{}", format_report(&session, &results, color));
+    if let Some(path) = &args.output {
+        fs::write(path, format_report(&session, &results, false))
+            .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+        info!("Report saved to '{}'", path);
+    }
+    Ok(())
+}
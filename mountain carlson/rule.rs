@@ -0,0 +1,243 @@
+use crate::deck::{rank_index, Deck, ACE_HIGH};
+
+/// Decides, after each card, whether a row stops drawing. The classic game
+/// stops once the total passes 30 (`SumThreshold`); the simulator and the
+/// exact solvers play whichever rule they are given.
+///
+/// A rule must depend only on its arguments: the exact solvers memoize
+/// states by total and remaining cards, which is sound only then.
+pub trait StoppingRule: Sync {
+    /// Whether to stop with the total at `sum` after `cards_drawn` cards,
+    /// the last of them worth `last_card` points.
+    fn should_stop(&self, sum: u16, cards_drawn: u16, last_card: u8) -> bool;
+
+    /// The highest total the row can still draw from, if the rule caps it.
+    /// Used only to size integers; `None` means the whole deck may be drawn.
+    fn sum_limit(&self) -> Option<u16> {
+        None
+    }
+}
+
+/// The classic rule: stop once the total exceeds the threshold.
+///
+/// The threshold is the highest total a row keeps drawing on, so the
+/// classic 30 stops a row on its first total of 31 or more. Every solver,
+/// the simulator and the shoe stop rows through `should_stop`, so this is
+/// the one place the convention is written down; `--threshold` and config
+/// files take the threshold in this sense.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SumThreshold(pub u16);
+
+impl SumThreshold {
+    /// The lowest total a row can stop on, the total of a refait.
+    pub fn lowest_stop(self) -> u16 {
+        self.0 + 1
+    }
+}
+
+impl Default for SumThreshold {
+    fn default() -> Self {
+        SumThreshold(DEFAULT_THRESHOLD.into())
+    }
+}
+
+/// The threshold of the classic game, and of every `--threshold` not given.
+pub const DEFAULT_THRESHOLD: u8 = 30;
+
+/// Thresholds every `--threshold` accepts, 21 for a blackjack-like game as
+/// well as the classic 30; above 245 a final ten could overflow a `u8`
+/// score.
+pub const THRESHOLD_RANGE: std::ops::RangeInclusive<i64> = 1..=245;
+
+impl StoppingRule for SumThreshold {
+    fn should_stop(&self, sum: u16, _cards_drawn: u16, _last_card: u8) -> bool {
+        sum > self.0
+    }
+
+    fn sum_limit(&self) -> Option<u16> {
+        Some(self.0)
+    }
+}
+
+/// Stop after a fixed number of cards, whatever the total.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CardLimit(pub u16);
+
+impl StoppingRule for CardLimit {
+    fn should_stop(&self, _sum: u16, cards_drawn: u16, _last_card: u8) -> bool {
+        cards_drawn >= self.0
+    }
+}
+
+/// Stop as soon as a card worth the given points is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StopOnCard(pub u8);
+
+impl StoppingRule for StopOnCard {
+    fn should_stop(&self, _sum: u16, _cards_drawn: u16, last_card: u8) -> bool {
+        last_card == self.0
+    }
+}
+
+/// `rule` for a row that already holds `start` points before its first
+/// card, as after an opening card dealt face up. The solvers count the
+/// total from 0, so they see the points drawn from here on and the caller
+/// adds `start` to the scores they find.
+#[derive(Clone, Copy)]
+pub struct Started<'a> {
+    pub rule: &'a dyn StoppingRule,
+    pub start: u16,
+}
+
+impl StoppingRule for Started<'_> {
+    fn should_stop(&self, sum: u16, cards_drawn: u16, last_card: u8) -> bool {
+        self.rule.should_stop(sum.saturating_add(self.start), cards_drawn, last_card)
+    }
+
+    fn sum_limit(&self) -> Option<u16> {
+        self.rule.sum_limit().map(|limit| limit.saturating_sub(self.start))
+    }
+}
+
+/// Aces scored as soft totals are in blackjack: an ace counts `ACE_HIGH`
+/// when that keeps the row within `bust`, and its own points otherwise. An
+/// ace held high drops back to its own points when a later card would take
+/// the row past `bust`, and the row carries on. The rule stops each row on
+/// the total so counted. Cards scoring what an ace does count as aces.
+///
+/// A blackjack dealer draws to 17 and busts past 21: a threshold of 16 with
+/// `bust` five past it. With `bust` at the threshold no row stops on a high
+/// ace, and ten or more past it no ace ever drops back, as no single card
+/// takes a row from the threshold that far.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SoftAces {
+    /// Points an ace scores counted low.
+    pub ace: u8,
+    pub bust: u16,
+}
+
+impl SoftAces {
+    /// Soft aces scoring `ace` low, for rows stopped by `rule`, busting
+    /// `reach` points past its `sum_limit`; a rule with no limit never busts.
+    pub fn new(ace: u8, rule: &dyn StoppingRule, reach: u16) -> Self {
+        SoftAces {
+            ace,
+            bust: rule.sum_limit().map_or(u16::MAX, |limit| limit.saturating_add(reach)),
+        }
+    }
+
+    /// The soft aces of `deck` under `rule`, if its aces count soft.
+    pub fn of(deck: &Deck, rule: &dyn StoppingRule) -> Option<Self> {
+        deck.soft_reach().map(|reach| SoftAces::new(deck.values()[rank_index(1)], rule, reach))
+    }
+
+    /// Points an ace gains by counting high.
+    pub fn bonus(self) -> u16 {
+        ACE_HIGH.saturating_sub(self.ace).into()
+    }
+
+    /// The total once a card scoring `points` lands on `total`, `high`
+    /// saying whether an ace in it counts high, with whether one does after.
+    pub fn draw(self, total: u16, high: bool, points: u8) -> (u16, bool) {
+        let total = total + u16::from(points);
+        if points == self.ace && !high && total + self.bonus() <= self.bust {
+            (total + self.bonus(), true)
+        } else if high && total > self.bust {
+            (total - self.bonus(), false)
+        } else {
+            (total, high)
+        }
+    }
+}
+
+/// The reach `Deck::with_soft_aces` takes for soft aces busting past `bust`
+/// under a threshold of `threshold`, as `--soft-aces` gives them.
+pub fn soft_reach(bust: u16, threshold: u16) -> Result<u16, String> {
+    bust.checked_sub(threshold).ok_or_else(|| {
+        format!(
+            "soft aces bust past {}, below the threshold of {}; a row must reach the \
+             threshold before it can bust",
+            bust, threshold
+        )
+    })
+}
+
+/// The highest total a row dealt from `deck` under `rule` can reach.
+pub fn max_total(rule: &dyn StoppingRule, deck: &Deck) -> u32 {
+    let points = deck.max_points();
+    match rule.sum_limit() {
+        Some(limit) => points.min(limit as u32 + deck.max_value() as u32),
+        None => points,
+    }
+}
+
+/// Whether every row dealt from `deck` under `rule` runs out of cards before
+/// the rule stops it: the rule caps the total and the whole deck stays
+/// within the cap, even with every joker at its most. Each row then scores
+/// the whole deck, a degenerate game worth flagging in a report.
+pub fn always_runs_out(rule: &dyn StoppingRule, deck: &Deck) -> bool {
+    rule.sum_limit().is_some_and(|limit| deck.max_points() <= limit as u32)
+}
+
+/// How many states a memoizing solver visits for `deck` under `rule`, if the
+/// rule caps the total: one per multiset of drawn cards whose points stay
+/// within `sum_limit`, since the running total and cards drawn follow from
+/// the multiset. Used to reserve memo capacity up front.
+pub fn reachable_states(rule: &dyn StoppingRule, deck: &Deck) -> Option<usize> {
+    let limit = rule.sum_limit()? as usize;
+    // ways[s]: multisets of the ranks seen so far with exactly s points.
+    let mut ways = vec![0usize; limit + 1];
+    ways[0] = 1;
+    for (&count, &value) in deck.counts().iter().zip(&deck.values()) {
+        let value = value as usize;
+        let mut next = vec![0usize; limit + 1];
+        for (s, &w) in ways.iter().enumerate().filter(|&(_, &w)| w > 0) {
+            for taken in 0..=count as usize {
+                let total = s + taken * value;
+                if total > limit {
+                    break;
+                }
+                next[total] = next[total].saturating_add(w);
+            }
+        }
+        ways = next;
+    }
+    Some(ways.iter().fold(0usize, |acc, &w| acc.saturating_add(w)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A blackjack dealer: drawing to 17, busting past 21.
+    fn dealer() -> SoftAces {
+        SoftAces::new(1, &SumThreshold(16), soft_reach(21, 16).unwrap())
+    }
+
+    #[test]
+    fn a_high_ace_drops_back_rather_than_bust() {
+        let soft = dealer();
+        // A 5 and an ace make a soft 16, and the row draws on.
+        let (total, high) = soft.draw(5, false, 1);
+        assert_eq!((total, high), (16, true));
+        assert!(!SumThreshold(16).should_stop(total, 2, 1));
+        // An 8 would bust a soft 16, so the ace counts 1 for a hard 14.
+        assert_eq!(soft.draw(total, high, 8), (14, false));
+        // A 4 makes a soft 20 instead, which stops the row without busting.
+        assert_eq!(soft.draw(total, high, 4), (20, true));
+    }
+
+    #[test]
+    fn an_ace_counts_low_when_high_would_bust() {
+        let soft = dealer();
+        assert_eq!(soft.draw(12, false, 1), (13, false));
+        // A second ace stays low beside one already high.
+        assert_eq!(soft.draw(13, true, 1), (14, true));
+    }
+
+    #[test]
+    fn soft_aces_cannot_bust_below_the_threshold() {
+        assert_eq!(soft_reach(30, 30), Ok(0));
+        assert!(soft_reach(21, 30).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::deck::{rank, rank_index, Deck, RANKS};
+use crate::rule::{StoppingRule, SumThreshold};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// One row of a coup: the total it stopped on and how many cards it took.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Row {
+    pub total: u8,
+    pub cards: u8,
+}
+
+/// How a coup is settled. The row with the lower total wins.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Noir,
+    Rouge,
+    /// Equal totals above the lowest possible one; bets are returned.
+    Tie,
+    /// Both rows on the lowest possible total (31-31 in the classic game).
+    Refait,
+}
+
+impl Outcome {
+    /// Every outcome, in report order.
+    pub const ALL: [Outcome; 4] = [Outcome::Noir, Outcome::Rouge, Outcome::Tie, Outcome::Refait];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Noir => "Noir",
+            Outcome::Rouge => "Rouge",
+            Outcome::Tie => "Tie",
+            Outcome::Refait => "Refait",
+        }
+    }
+}
+
+/// A full coup: the Noir row is dealt first, then the Rouge row.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Coup {
+    pub noir: Row,
+    pub rouge: Row,
+    /// The color of the first card of the Noir row, which settles the
+    /// couleur and inverse bets.
+    pub first_card_black: bool,
+}
+
+impl Coup {
+    /// Settles the coup for rows that stopped once their total exceeded `threshold`.
+    pub fn outcome(&self, threshold: u8) -> Outcome {
+        let (noir, rouge) = (self.noir.total, self.rouge.total);
+        if noir < rouge {
+            Outcome::Noir
+        } else if rouge < noir {
+            Outcome::Rouge
+        } else if u16::from(noir) == SumThreshold(threshold.into()).lowest_stop() {
+            Outcome::Refait
+        } else {
+            Outcome::Tie
+        }
+    }
+}
+
+/// The card burned off the top of every fresh shoe, unseen, before the
+/// first coup is dealt.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Burn {
+    /// Whatever card the shuffle put on top.
+    Random,
+    /// A card of this rank, taken out before the shuffle.
+    Rank(u8),
+}
+
+/// Parses a `--burn` argument: `random`, or the rank of the card to burn.
+pub fn parse_burn(arg: &str) -> Result<Burn, String> {
+    if arg == "random" {
+        return Ok(Burn::Random);
+    }
+    arg.parse()
+        .ok()
+        .filter(|rank| (1..=RANKS as u8).contains(rank))
+        .map(Burn::Rank)
+        .ok_or_else(|| format!("expected random or a rank in 1..={}, found '{}'", RANKS, arg))
+}
+
+/// Share of the shoe dealt before the cut card comes out, unless
+/// `--penetration` says otherwise: three quarters, as casinos commonly cut.
+pub const DEFAULT_PENETRATION: f64 = 0.75;
+
+/// Parses a `--penetration` argument: the share of the shoe dealt before
+/// the reshuffle, above 0 and at most 1.
+pub fn parse_penetration(arg: &str) -> Result<f64, String> {
+    arg.parse()
+        .ok()
+        .filter(|fraction| *fraction > 0.0 && *fraction <= 1.0)
+        .ok_or_else(|| format!("expected a fraction above 0 and at most 1, found '{}'", arg))
+}
+
+/// A card in the shoe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Card {
+    points: u8,
+    black: bool,
+}
+
+/// A shoe dealt coup after coup until it runs out, then reshuffled.
+pub struct Shoe {
+    cards: Vec<Card>,
+    next: usize,
+    /// What each shuffle burns: `None` nothing, `Some(None)` the top card,
+    /// `Some(Some(points))` a card scoring `points`.
+    burn: Option<Option<u8>>,
+    /// Where the cut card sits: no coup starts once this many cards are
+    /// dealt, though one started before it is dealt to its end.
+    cut: usize,
+}
+
+impl Shoe {
+    /// A shoe holding the cards of `deck`, in ascending order until shuffled.
+    /// The cards of each value are split evenly between the colors, the
+    /// odd one out being black.
+    pub fn new(deck: &Deck) -> Self {
+        let points = deck.cards();
+        let cards = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| Card {
+                points: p,
+                // The cards come sorted, so this alternates within each value.
+                black: (i - points.partition_point(|&q| q < p)) % 2 == 0,
+            })
+            .collect::<Vec<_>>();
+        Shoe {
+            cut: cards.len(),
+            cards,
+            next: 0,
+            burn: None,
+        }
+    }
+
+    /// The same shoe reshuffled once `penetration`, a fraction of its
+    /// cards, has been dealt, rather than only when it runs out.
+    pub fn with_penetration(mut self, penetration: f64) -> Self {
+        self.cut = (self.cards.len() as f64 * penetration).ceil() as usize;
+        self
+    }
+
+    /// The same shoe burning a card after every shuffle. `deck` is the deck
+    /// the shoe was built from, which must hold a card of a burned rank.
+    pub fn with_burn(mut self, burn: Burn, deck: &Deck) -> Self {
+        self.burn = Some(match burn {
+            Burn::Random => None,
+            Burn::Rank(rank) => Some(deck.values()[rank_index(rank)]),
+        });
+        self
+    }
+
+    /// Gathers every card back and shuffles, then burns a card if the shoe
+    /// burns one. The burned card counts as dealt.
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) {
+        match self.burn {
+            Some(Some(points)) => {
+                // Set the burned card on top and shuffle the rest under it.
+                let i = self
+                    .cards
+                    .iter()
+                    .position(|card| card.points == points)
+                    .expect("the burned rank is in the shoe");
+                self.cards.swap(0, i);
+                self.cards[1..].shuffle(rng);
+            }
+            _ => self.cards.shuffle(rng),
+        }
+        self.next = usize::from(self.burn.is_some());
+    }
+
+    /// Cards not dealt since the last shuffle.
+    pub fn remaining(&self) -> usize {
+        self.cards.len() - self.next
+    }
+
+    /// Whether the cut card has come out, so the shoe deals no more coups
+    /// until it is reshuffled. The first coup of a shoe is always dealt.
+    pub fn at_cut(&self) -> bool {
+        self.next >= self.cut && self.next > usize::from(self.burn.is_some())
+    }
+
+    /// The cards of `deck`, the deck this shoe was built from, that have
+    /// not been dealt since the last shuffle. The shoe holds points, not
+    /// ranks, so a dealt card is taken from the first rank scoring its
+    /// points; ranks scoring alike are interchangeable to every total.
+    pub fn residual(&self, deck: &Deck) -> Deck {
+        let values = deck.values();
+        let mut left = Deck::from_counts(deck.counts()).with_values(values);
+        for card in &self.cards[..self.next] {
+            let counts = left.counts();
+            let i = (0..RANKS)
+                .find(|&i| values[i] == card.points && counts[i] > 0)
+                .expect("the shoe was built from this deck");
+            left.remove(rank(i), 1).expect("the rank holds a card");
+        }
+        left
+    }
+
+    /// Deals one row, or `None` if the shoe runs out first.
+    fn deal_row(&mut self, rule: &dyn StoppingRule) -> Option<Row> {
+        let mut row = Row { total: 0, cards: 0 };
+        loop {
+            let card = self.cards.get(self.next)?;
+            self.next += 1;
+            row.total += card.points;
+            row.cards += 1;
+            if rule.should_stop(row.total.into(), row.cards.into(), card.points) {
+                return Some(row);
+            }
+        }
+    }
+
+    /// Deals both rows of a coup, or `None` if the cut card is out or the
+    /// shoe runs out part way; such a coup does not count and the shoe must
+    /// be reshuffled.
+    pub fn deal_coup(&mut self, threshold: u8) -> Option<Coup> {
+        let rule = SumThreshold(threshold.into());
+        self.deal_coup_by(&rule, &rule)
+    }
+
+    /// `deal_coup` for rows that stop by rules of their own, as in variants
+    /// where the second row draws to a different total.
+    pub fn deal_coup_by(&mut self, noir: &dyn StoppingRule, rouge: &dyn StoppingRule) -> Option<Coup> {
+        if self.at_cut() {
+            return None;
+        }
+        let first_card_black = self.cards.get(self.next)?.black;
+        let noir = self.deal_row(noir)?;
+        let rouge = self.deal_row(rouge)?;
+        Some(Coup {
+            noir,
+            rouge,
+            first_card_black,
+        })
+    }
+}
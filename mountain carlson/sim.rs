@@ -0,0 +1,1971 @@
+use clap::{Args, ValueEnum};
+use crate::error::{first_error, Error, Result};
+use log::{error, info, warn};
+use monte_carlo_sim::color;
+use monte_carlo_sim::game::{play_game, BatchRng};
+use monte_carlo_sim::deck::{
+    self, Deck, JokerValue, PointWeights, ACE_HIGH, DEFAULT_VALUES, RANKS,
+};
+use monte_carlo_sim::latex;
+use monte_carlo_sim::npy;
+use monte_carlo_sim::rule::{
+    self, SoftAces, StoppingRule, SumThreshold, DEFAULT_THRESHOLD, THRESHOLD_RANGE,
+};
+use monte_carlo_sim::table::{argmax, Align, Table};
+use monte_carlo_sim::variant::{self, Variant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Magic bytes at the start of every binary results file.
+const BINARY_MAGIC: &[u8; 4] = b"TEQR";
+
+/// Bumped whenever the serialized layout of `SimResults` changes.
+const BINARY_FORMAT_VERSION: u32 = 3;
+
+/// How many games ended on each value of a score or length, indexed by the
+/// value itself. A plain array keeps hashing out of the per-game hot path;
+/// one slot per `u8` means indexing never needs a bounds check.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Counts([u64; 256]);
+
+impl Counts {
+    /// Games that ended on `value`.
+    pub fn get(&self, value: u8) -> u64 {
+        self.0[value as usize]
+    }
+
+    fn add(&mut self, value: u8, count: u64) {
+        self.0[value as usize] += count;
+    }
+
+    /// Every value seen at least once with its count, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        (0..=u8::MAX)
+            .zip(self.0.iter().copied())
+            .filter(|&(_, count)| count > 0)
+    }
+}
+
+impl Default for Counts {
+    fn default() -> Self {
+        Counts([0; 256])
+    }
+}
+
+// Serialized as the observed `(value, count)` pairs, the layout the earlier
+// `HashMap<u8, u64>` had under bincode; files of an older
+// `BINARY_FORMAT_VERSION` are refused all the same. Bincode writes the
+// length first, so the pairs are collected: the filtered iterator cannot
+// tell it up front.
+impl Serialize for Counts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().collect::<Vec<_>>())
+    }
+}
+
+impl<'de> Deserialize<'de> for Counts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut counts = Counts::default();
+        for (value, count) in Vec::<(u8, u64)>::deserialize(deserializer)? {
+            counts.add(value, count);
+        }
+        Ok(counts)
+    }
+}
+
+/// How the games were played, beyond the cards dealt: games played under
+/// other rules are other games, which results may not merge or resume.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayRules {
+    /// The highest total a row draws on, the threshold of a `SumThreshold`.
+    pub threshold: Option<u16>,
+    /// Points a joker scores, 0 for `JokerValue::Best` and for decks
+    /// without jokers.
+    pub joker: u8,
+    /// How far past the threshold soft aces bust, if the aces count soft.
+    pub soft_aces: Option<u16>,
+    /// Draw weight of each card by its points, for the points the deck
+    /// holds, if the draw is weighted.
+    pub weights: Option<Vec<(u8, f64)>>,
+}
+
+impl PlayRules {
+    /// The rules `deck` is played under with rows stopped by `rule`. A
+    /// weighted deck must have passed `check_game_width`.
+    pub fn of(deck: &Deck, rule: &dyn StoppingRule) -> Self {
+        let joker = match deck.joker_value() {
+            JokerValue::Fixed(points) if deck.jokers() > 0 => points,
+            _ => 0,
+        };
+        let weights = draw_weights(deck).map(|weights| {
+            let mut points = deck.cards();
+            points.sort_unstable();
+            points.dedup();
+            points.into_iter().map(|p| (p, weights[p as usize])).collect()
+        });
+        PlayRules {
+            threshold: rule.sum_limit(),
+            joker,
+            soft_aces: deck.soft_reach(),
+            weights,
+        }
+    }
+}
+
+impl fmt::Display for PlayRules {
+    /// Formats as e.g. `threshold 30, jokers scoring 5, soft aces busting 5
+    /// past it, weights 1=1,10=1.05`, leaving out the rules not in play.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.threshold {
+            Some(threshold) => write!(f, "threshold {}", threshold)?,
+            None => write!(f, "no threshold")?,
+        }
+        if self.joker > 0 {
+            write!(f, ", jokers scoring {}", self.joker)?;
+        }
+        if let Some(reach) = self.soft_aces {
+            write!(f, ", soft aces busting {} past it", reach)?;
+        }
+        if let Some(weights) = &self.weights {
+            let pairs: Vec<String> = weights.iter().map(|(p, w)| format!("{}={}", p, w)).collect();
+            write!(f, ", weights {}", pairs.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Holds the counts of all observed outcomes from the simulation.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimResults {
+    /// The card values of the deck that was played, sorted.
+    pub deck: Vec<u8>,
+    pub rules: PlayRules,
+    pub score_counts: Counts,
+    pub length_counts: Counts,
+    pub total_games: u64,
+}
+
+impl SimResults {
+    /// No games yet of `deck` played with rows stopped by `rule`.
+    pub fn new(deck: &Deck, rule: &dyn StoppingRule) -> Self {
+        let mut cards = deck.cards();
+        cards.sort_unstable();
+        SimResults {
+            deck: cards,
+            rules: PlayRules::of(deck, rule),
+            score_counts: Counts::default(),
+            length_counts: Counts::default(),
+            total_games: 0,
+        }
+    }
+
+    /// No games yet, of the same deck and rules as `self`.
+    fn emptied(&self) -> Self {
+        SimResults {
+            deck: self.deck.clone(),
+            rules: self.rules.clone(),
+            score_counts: Counts::default(),
+            length_counts: Counts::default(),
+            total_games: 0,
+        }
+    }
+
+    /// Counts one finished game.
+    fn record(&mut self, score: u8, length: u8) {
+        self.total_games += 1;
+        self.score_counts.add(score, 1);
+        self.length_counts.add(length, 1);
+    }
+
+    /// Checks that `other` counts the same games as `self`: the same deck
+    /// played under the same rules.
+    fn check_compatible(&self, other: &SimResults) -> std::result::Result<(), String> {
+        if self.deck != other.deck {
+            return Err(format!(
+                "incompatible decks: {} cards vs {} cards with different values",
+                self.deck.len(),
+                other.deck.len()
+            ));
+        }
+        if self.rules != other.rules {
+            return Err(format!("incompatible rules: {} vs {}", self.rules, other.rules));
+        }
+        Ok(())
+    }
+
+    /// Adds the counts of `other` into `self`. Both must come from the same deck
+    /// and rules, otherwise the samples are not comparable and nothing is changed.
+    pub fn merge(&mut self, other: &SimResults) -> std::result::Result<(), String> {
+        self.check_compatible(other)?;
+        for (score, count) in other.score_counts.iter() {
+            self.score_counts.add(score, count);
+        }
+        for (length, count) in other.length_counts.iter() {
+            self.length_counts.add(length, count);
+        }
+        self.total_games += other.total_games;
+        Ok(())
+    }
+
+    /// Mean and standard deviation of the final score, or `None` if no
+    /// game was played.
+    fn score_stats(&self) -> Option<(f64, f64)> {
+        mean_and_std_dev(&self.score_counts, self.total_games)
+    }
+
+    /// Mean and standard deviation of the game length in cards, or `None`
+    /// if no game was played.
+    fn length_stats(&self) -> Option<(f64, f64)> {
+        mean_and_std_dev(&self.length_counts, self.total_games)
+    }
+
+    /// The `n` most frequent values of `counts` with their probabilities,
+    /// most probable first (ties broken by the smaller value).
+    fn top_values(&self, counts: &Counts, n: usize) -> Vec<(u8, f64)> {
+        let mut sorted: Vec<_> = counts.iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sorted
+            .into_iter()
+            .take(n)
+            .map(|(v, c)| (v, c as f64 / self.total_games as f64))
+            .collect()
+    }
+
+    /// Score probabilities indexed by score, zero for scores never seen.
+    fn score_pmf(&self) -> Vec<f64> {
+        let max_score = self.score_counts.iter().last().map_or(0, |(score, _)| score);
+        let mut pmf = vec![0.0; max_score as usize + 1];
+        for (score, count) in self.score_counts.iter() {
+            pmf[score as usize] = count as f64 / self.total_games as f64;
+        }
+        pmf
+    }
+
+    /// Saves the full counts in a compact binary form meant for checkpoints,
+    /// not for reading: a magic tag, a format version, then the bincode body.
+    fn save_binary(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&BINARY_FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+        writer.flush()
+    }
+
+    /// Loads results written by `save_binary`, rejecting foreign files and
+    /// files written with a different format version.
+    fn load_binary(path: &str) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != BINARY_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a simulation results file",
+            ));
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != BINARY_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported results format version {} (expected {})",
+                    version, BINARY_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        bincode::deserialize_from(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Mean and population standard deviation of a value-to-count histogram,
+/// or `None` for an empty one rather than the NaNs of dividing by zero.
+fn mean_and_std_dev(counts: &Counts, total: u64) -> Option<(f64, f64)> {
+    if total == 0 {
+        return None;
+    }
+    let n = total as f64;
+    let mean = counts
+        .iter()
+        .map(|(value, count)| value as f64 * count as f64)
+        .sum::<f64>()
+        / n;
+    let variance = counts
+        .iter()
+        .map(|(value, count)| (value as f64 - mean).powi(2) * count as f64)
+        .sum::<f64>()
+        / n;
+    Some((mean, variance.sqrt()))
+}
+
+/// What the reports print in place of a statistic when no game was played.
+const NO_DATA: &str = "no data";
+
+/// The mean of `stats` to four places, or `NO_DATA`.
+fn format_mean(stats: Option<(f64, f64)>, unit: &str) -> String {
+    stats.map_or(NO_DATA.to_string(), |(mean, _)| format!("{:.4}{}", mean, unit))
+}
+
+/// How much the simulator prints to the console.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    /// Only the final one-line summary.
+    Quiet,
+    Normal,
+    /// Running statistics at every progress interval.
+    Verbose,
+}
+
+/// The file format the final results are saved in.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputFormat {
+    /// The human-readable report, as printed to the console.
+    Text,
+    /// Score and length distributions as columnar data.
+    Parquet,
+    /// The score distribution as a booktabs `tabular`.
+    Latex,
+    /// Averages and both distributions as one JSON object.
+    Json,
+    /// One `kind,value,count,probability` row per score and length.
+    Csv,
+    /// Like `csv`, separated by tabs.
+    Tsv,
+    /// The report with GitHub-flavored Markdown tables.
+    Markdown,
+}
+
+impl OutputFormat {
+    /// The file the results are written to unless `--output` says otherwise.
+    fn default_path(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "monte_carlo_results.txt",
+            OutputFormat::Parquet => "monte_carlo_results.parquet",
+            OutputFormat::Latex => "monte_carlo_results.tex",
+            OutputFormat::Json => "monte_carlo_results.json",
+            OutputFormat::Csv => "monte_carlo_results.csv",
+            OutputFormat::Tsv => "monte_carlo_results.tsv",
+            OutputFormat::Markdown => "monte_carlo_results.md",
+        }
+    }
+}
+
+/// Options of a simulation run.
+#[derive(Args)]
+pub struct RunArgs {
+    /// Stop after this many games instead of waiting for Ctrl+C.
+    #[arg(long, env = "TRENTE_GAMES")]
+    games: Option<u64>,
+
+    /// Read defaults for these options from a TOML file. Flags given on the
+    /// command line win over `TRENTE_*` environment variables, which win
+    /// over the file.
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+
+    /// Stop drawing once the total exceeds this value [default: 30].
+    #[arg(long, value_parser = clap::value_parser!(u8).range(THRESHOLD_RANGE))]
+    threshold: Option<u8>,
+
+    /// Seed for the random number generator; random when omitted.
+    #[arg(long, env = "TRENTE_SEED")]
+    seed: Option<u64>,
+
+    /// Worker threads playing games [default: one per CPU].
+    #[arg(long, env = "TRENTE_THREADS", value_parser = clap::value_parser!(u64).range(1..=1024))]
+    threads: Option<u64>,
+
+    /// Random words each worker generates at a time, or 0 to draw them one
+    /// by one; the games played are the same either way [default: 0].
+    #[arg(long, value_name = "WORDS", value_parser = clap::value_parser!(u64).range(0..=1 << 20))]
+    rng_batch: Option<u64>,
+
+    /// Deck to deal from: spanish-40, standard-40, standard-52, six-deck,
+    /// eight-deck, thirteen-52, dice-36, primes-24, or a deck file of
+    /// `value=count` lines (or a .json/.toml map), or `-` for stdin
+    /// [default: spanish-40].
+    #[arg(long, value_parser = deck::parse_arg)]
+    deck: Option<Deck>,
+
+    /// Named game to play, setting the deck, threshold and aces together:
+    /// classic-6deck, spanish-40, simplified-40 or blackjack-21. Taken by
+    /// exact, sim and compare only.
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = variant::parse_arg,
+        conflicts_with_all = ["deck", "threshold"],
+    )]
+    variant: Option<Variant>,
+
+    /// Points each rank scores, as `rank=points` pairs such as `1=11`; ranks
+    /// not listed score their face value.
+    #[arg(long, value_parser = deck::parse_values)]
+    values: Option<[u8; RANKS]>,
+
+    /// Count aces as 11 points instead of 1, whatever --values says.
+    #[arg(long)]
+    aces_high: bool,
+
+    /// Count aces as soft totals busting past BUST: 11 while that keeps the
+    /// row at or under BUST, dropping back to 1 when a later card would take
+    /// it past. A blackjack dealer is `--threshold 16 --soft-aces 21`.
+    #[arg(long, value_name = "BUST", conflicts_with = "aces_high")]
+    soft_aces: Option<u16>,
+
+    /// Jokers to shuffle into the deck [default: 0].
+    #[arg(long)]
+    jokers: Option<u16>,
+
+    /// Points a joker scores: `best` for the points that take the row
+    /// nearest the threshold's stopping total, or a fixed value [default: best].
+    #[arg(long, value_parser = deck::parse_joker_value)]
+    joker_value: Option<JokerValue>,
+
+    /// Draw weight of each rank, as `rank=weight` pairs such as `10=1.05`,
+    /// for a shuffle that favors some ranks; ranks not listed weigh 1.
+    #[arg(long, value_parser = deck::parse_weights)]
+    weights: Option<[f64; RANKS]>,
+
+    /// Cards already dealt from the deck, as `rank=count` pairs such as
+    /// `1=4,10=12`, to play from the shoe part way through.
+    #[arg(long, value_name = "RANK=COUNT", value_parser = deck::parse_removed)]
+    remove: Option<BTreeMap<u8, u16>>,
+
+    /// File the results are saved to [default: monte_carlo_results.<ext>].
+    #[arg(long, value_name = "FILE", env = "TRENTE_OUTPUT")]
+    output: Option<String>,
+
+    /// Format of the saved results [default: text].
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Caption for --format latex.
+    #[arg(long)]
+    caption: Option<String>,
+
+    /// Print the resolved settings and exit without simulating.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Time a fixed workload (--games, or 4000000) at 1, 2, 4, ... threads
+    /// up to --threads and print games per second and speedup, then exit.
+    #[arg(long, conflicts_with = "dry_run")]
+    bench_threads: bool,
+
+    /// Print only a one-line summary of averages and standard deviations.
+    #[arg(long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print running statistics at every progress interval.
+    #[arg(long)]
+    verbose: bool,
+
+    /// Stream one JSON object per game to FILE, or to stdout without one
+    /// (the report is then only saved, not printed).
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    ndjson: Option<String>,
+
+    /// Continue counting on top of a binary snapshot, which must have been
+    /// played with the same deck, threshold and rule options.
+    #[arg(long, value_name = "FILE")]
+    resume: Option<String>,
+
+    /// Also save the final counts in binary form.
+    #[arg(long, value_name = "FILE")]
+    save_binary: Option<String>,
+
+    /// Append the run to a SQLite archive (needs the `sqlite` feature).
+    #[arg(long, value_name = "FILE")]
+    sqlite: Option<String>,
+
+    /// Also save the score probabilities as a NumPy array.
+    #[arg(long, value_name = "FILE")]
+    npy: Option<String>,
+}
+
+/// Checks that a game's `u8` score and length cannot overflow, so a row
+/// may score at most 255 points and last at most 255 cards. Under a
+/// `SumThreshold` the last card adds at most `deck.max_value()` points on
+/// top of the threshold; with the classic face values `THRESHOLD_RANGE`
+/// already guarantees this, but higher ranks, `--values` and other rules
+/// may not. A weighted deck must also weigh alike the ranks that score
+/// alike, since `play_game` knows a card only by its points, and soft aces
+/// are not dealt with jokers, which no exact solver can check them against.
+pub fn check_game_width(deck: &Deck, rule: &dyn StoppingRule) -> Result<()> {
+    if rule::max_total(rule, deck) > u8::MAX as u32 {
+        return Err(Error::Invalid(match rule.sum_limit() {
+            Some(_) => format!(
+                "With cards worth up to {} points the threshold can be at most {}",
+                deck.max_value(),
+                u8::MAX.saturating_sub(deck.max_value())
+            ),
+            None => format!("A game could score more than {} points", u8::MAX),
+        }));
+    }
+    let max_cards = rule
+        .sum_limit()
+        .map_or(deck.len(), |limit| deck.len().min(limit as usize + 1));
+    if max_cards > u8::MAX as usize {
+        return Err(Error::Invalid(format!(
+            "A game could last more than {} cards",
+            u8::MAX
+        )));
+    }
+    if deck.soft_aces() && deck.jokers() > 0 {
+        return Err(Error::Invalid("Jokers and soft aces cannot be dealt together".to_string()));
+    }
+    if deck.is_weighted() {
+        deck.point_weights().map_err(Error::Invalid)?;
+    }
+    Ok(())
+}
+
+/// The weights `play_game` draws the cards of `deck` by, or `None` for a
+/// fair draw. The deck must have passed `check_game_width`.
+fn draw_weights(deck: &Deck) -> Option<PointWeights> {
+    deck.is_weighted()
+        .then(|| deck.point_weights().expect("checked by check_game_width"))
+}
+
+/// The experiment definition read by `--config`. Every key is optional:
+///
+/// ```toml
+/// deck = "six-deck"        # or an array of counts, from the aces up
+/// values = "1=11"          # points per rank, as for --values
+/// aces_high = true         # as --aces-high
+/// soft_aces = 21           # the total soft aces bust past, as --soft-aces
+/// jokers = 2
+/// joker_value = "best"     # or a number of points, as for --joker-value
+/// weights = "10=1.05"      # draw weight per rank, as for --weights
+/// remove = "1=4,10=12"     # cards already dealt, as for --remove
+/// threshold = 30
+/// games = 1000000
+/// seed = 42
+/// threads = 4
+/// format = "latex"
+/// output = "results.tex"
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RunConfig {
+    deck: Option<Deck>,
+    #[serde(default, deserialize_with = "deserialize_values")]
+    values: Option<[u8; RANKS]>,
+    aces_high: Option<bool>,
+    soft_aces: Option<u16>,
+    jokers: Option<u16>,
+    #[serde(default, deserialize_with = "deserialize_joker_value")]
+    joker_value: Option<JokerValue>,
+    #[serde(default, deserialize_with = "deserialize_weights")]
+    weights: Option<[f64; RANKS]>,
+    #[serde(default, deserialize_with = "deserialize_removed")]
+    remove: Option<BTreeMap<u8, u16>>,
+    threshold: Option<u8>,
+    games: Option<u64>,
+    seed: Option<u64>,
+    threads: Option<u64>,
+    format: Option<OutputFormat>,
+    output: Option<String>,
+}
+
+/// Reads the `values` key of a config file, written like `--values`.
+fn deserialize_values<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<[u8; RANKS]>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    deck::parse_values(&text).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Reads the `joker_value` key of a config file, written like `--joker-value`.
+fn deserialize_joker_value<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<JokerValue>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    deck::parse_joker_value(&text).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Reads the `weights` key of a config file, written like `--weights`.
+fn deserialize_weights<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<[f64; RANKS]>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    deck::parse_weights(&text).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Reads the `remove` key of a config file, written like `--remove`.
+fn deserialize_removed<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<BTreeMap<u8, u16>>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    deck::parse_removed(&text).map(Some).map_err(serde::de::Error::custom)
+}
+
+impl RunConfig {
+    /// Reads and parses a config file.
+    fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("Error reading config '{}': {}", path, e)))?;
+        let config: RunConfig = toml::from_str(&text)
+            .map_err(|e| Error::Invalid(format!("Error in config '{}': {}", path, e)))?;
+        if config.threads == Some(0) {
+            return Err(Error::Invalid(format!(
+                "Error in config '{}': threads must be at least 1",
+                path
+            )));
+        }
+        if let Some(threshold) = config.threshold {
+            if !THRESHOLD_RANGE.contains(&(threshold as i64)) {
+                return Err(Error::Invalid(format!(
+                    "Error in config '{}': threshold {} is not in {}..={}",
+                    path,
+                    threshold,
+                    THRESHOLD_RANGE.start(),
+                    THRESHOLD_RANGE.end()
+                )));
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl RunArgs {
+    /// Fills every option not given on the command line from `config`.
+    fn apply_config(&mut self, config: RunConfig) {
+        self.deck = self.deck.take().or(config.deck);
+        self.values = self.values.or(config.values);
+        // A flag cannot be given as false, so the file may only turn it on.
+        self.aces_high |= config.aces_high.unwrap_or(false);
+        self.soft_aces = self.soft_aces.or(config.soft_aces);
+        self.jokers = self.jokers.or(config.jokers);
+        self.joker_value = self.joker_value.or(config.joker_value);
+        self.weights = self.weights.or(config.weights);
+        self.remove = self.remove.take().or(config.remove);
+        self.threshold = self.threshold.or(config.threshold);
+        self.games = self.games.or(config.games);
+        self.seed = self.seed.or(config.seed);
+        self.threads = self.threads.or(config.threads);
+        self.format = self.format.or(config.format);
+        self.output = self.output.take().or(config.output);
+    }
+}
+
+/// Everything the end-of-run reporting needs.
+struct ReportSettings {
+    color: bool,
+    /// Whether the report is printed to stdout. Off while `--ndjson` streams
+    /// there, so stdout carries one kind of data only.
+    console: bool,
+    verbosity: Verbosity,
+    format: OutputFormat,
+    output: String,
+    caption: Option<String>,
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    seed: u64,
+    save_binary: Option<String>,
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    sqlite: Option<String>,
+    npy: Option<String>,
+}
+
+/// A destination for per-game NDJSON records, shared by the workers.
+type NdjsonSink = Arc<Mutex<NdjsonWriter>>;
+
+/// How long `--ndjson` records may sit in the writer's buffer.
+const NDJSON_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The `--ndjson` writer. Workers hand it each batch of records as they
+/// merge it, so the stream holds at most a batch per worker plus this
+/// buffer in memory, however long the run. The buffer is flushed once
+/// `NDJSON_FLUSH_INTERVAL` has passed, which keeps the file readable while
+/// it grows without a write per line.
+struct NdjsonWriter {
+    out: BufWriter<Box<dyn Write + Send>>,
+    last_flush: Instant,
+}
+
+impl NdjsonWriter {
+    fn new(out: Box<dyn Write + Send>) -> Self {
+        NdjsonWriter {
+            out: BufWriter::new(out),
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn write_records(&mut self, records: &str) -> io::Result<()> {
+        self.out.write_all(records.as_bytes())?;
+        if self.last_flush.elapsed() >= NDJSON_FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.out.flush()
+    }
+}
+
+/// Opens the `--ndjson` sink: stdout for `-`, otherwise the named file.
+fn open_ndjson_sink(path: &str) -> Result<NdjsonSink> {
+    let writer: Box<dyn Write + Send> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        let file = File::create(path)
+            .map_err(|e| Error::Io(format!("Error creating NDJSON file '{}': {}", path, e)))?;
+        Box::new(file)
+    };
+    Ok(Arc::new(Mutex::new(NdjsonWriter::new(writer))))
+}
+
+/// Builds a value/probability table from observed counts, sorted by value.
+pub fn distribution_table(label: &str, counts: &Counts, total_games: u64) -> Table {
+    let sorted: Vec<_> = counts.iter().collect();
+
+    let mut table = Table::new(&[(label, Align::Right), ("Probability", Align::Right)]);
+    for (value, count) in &sorted {
+        let prob = (*count as f64 / total_games as f64) * 100.0;
+        table.add_row(vec![value.to_string(), format!("{:.6}%", prob)]);
+    }
+    // The most probable value is highlighted on a color terminal.
+    if let Some(mode) = argmax(sorted.iter().map(|&(_, count)| count)) {
+        table.highlight_row(mode);
+    }
+    table
+}
+
+/// Formats the full report. With `color` set, headers are bolded and the most
+/// probable score and length highlighted; the file copy is always plain.
+pub fn format_report(results: &SimResults, color: bool) -> String {
+    // Prepare the output string
+    let mut output = String::new();
+    output.push_str(&color::bold("Monte Carlo Simulation Results", color));
+    output.push('\n');
+    output.push_str(&format!("Total Games Simulated: {}\n\n", results.total_games));
+
+    output.push_str(&color::bold("--- Averages ---", color));
+    output.push('\n');
+    output.push_str(&format!("Average Score:  {}\n", format_mean(results.score_stats(), "")));
+    output.push_str(&format!(
+        "Average Length: {}\n\n",
+        format_mean(results.length_stats(), " cards")
+    ));
+
+    let sections = [("Score", &results.score_counts), ("Length", &results.length_counts)];
+    for (i, (label, counts)) in sections.into_iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        output.push_str(&color::bold(&format!("--- {} Distribution ---", label), color));
+        output.push('\n');
+        if results.total_games == 0 {
+            output.push_str(NO_DATA);
+            output.push('\n');
+        } else {
+            output.push_str(
+                &distribution_table(label, counts, results.total_games).render_styled(color),
+            );
+        }
+    }
+    output
+}
+
+/// Calculates probabilities and saves them to a file and prints them to
+/// stdout. Status messages are logged to stderr, so stdout holds only
+/// results. `interrupted` tells a Ctrl+C stop from reaching `--games`.
+fn report_and_save_results(
+    results: &SimResults,
+    settings: &ReportSettings,
+    interrupted: bool,
+) -> Result<()> {
+    if settings.verbosity == Verbosity::Quiet {
+        if settings.console {
+            print_summary_line(results);
+        }
+        return save_results(results, settings);
+    }
+
+    if interrupted {
+        info!("Simulation interrupted");
+    } else {
+        info!("Simulation finished");
+    }
+    info!("Calculating results from {} total games played.", results.total_games);
+
+    if results.total_games == 0 {
+        warn!("No games were played; the report has no data.");
+    }
+
+    // Print to console; binary formats show the text report instead.
+    let caption = settings.caption.as_deref();
+    if settings.console {
+        let console = format_results(results, settings.format, caption, settings.color)
+            .unwrap_or_else(|| format_report(results, settings.color));
+        println!("{}", console);
+    }
+    save_results(results, settings)
+}
+
+/// The score distribution as a LaTeX table, with an optional caption.
+fn format_latex(results: &SimResults, caption: Option<&str>) -> String {
+    let rows: Vec<Vec<String>> = results
+        .score_counts
+        .iter()
+        .map(|(score, count)| {
+            let prob = count as f64 / results.total_games as f64 * 100.0;
+            vec![score.to_string(), format!("{:.6}", prob)]
+        })
+        .collect();
+    latex::tabular(
+        &[("Score", Align::Right), ("Probability (%)", Align::Right)],
+        &rows,
+        caption,
+    )
+}
+
+/// Every observed score, then every observed length, in ascending order, as
+/// `(kind, value, count, probability)` with `kind` "score" or "length".
+fn distribution_rows(results: &SimResults) -> Vec<(&'static str, u8, u64, f64)> {
+    let mut rows = Vec::new();
+    for (kind, counts) in [("score", &results.score_counts), ("length", &results.length_counts)] {
+        for (value, count) in counts.iter() {
+            rows.push((kind, value, count, count as f64 / results.total_games as f64));
+        }
+    }
+    rows
+}
+
+/// One observed value in the JSON export.
+#[derive(Serialize)]
+struct JsonEntry {
+    value: u8,
+    count: u64,
+    probability: f64,
+}
+
+/// The layout of `--format json`. The averages and standard deviations
+/// are `null` when no game was played.
+#[derive(Serialize)]
+struct JsonReport {
+    total_games: u64,
+    avg_score: Option<f64>,
+    sd_score: Option<f64>,
+    avg_length: Option<f64>,
+    sd_length: Option<f64>,
+    scores: Vec<JsonEntry>,
+    lengths: Vec<JsonEntry>,
+}
+
+/// Averages, standard deviations and both distributions as pretty JSON.
+fn format_json(results: &SimResults) -> String {
+    let score = results.score_stats();
+    let length = results.length_stats();
+    let mut report = JsonReport {
+        total_games: results.total_games,
+        avg_score: score.map(|(mean, _)| mean),
+        sd_score: score.map(|(_, sd)| sd),
+        avg_length: length.map(|(mean, _)| mean),
+        sd_length: length.map(|(_, sd)| sd),
+        scores: Vec::new(),
+        lengths: Vec::new(),
+    };
+    for (kind, value, count, probability) in distribution_rows(results) {
+        let entry = JsonEntry { value, count, probability };
+        match kind {
+            "score" => report.scores.push(entry),
+            _ => report.lengths.push(entry),
+        }
+    }
+    let mut out = serde_json::to_string_pretty(&report).expect("JSON values always serialize");
+    out.push('\n');
+    out
+}
+
+/// The distribution rows with a header line, fields joined by `separator`.
+fn format_delimited(results: &SimResults, separator: char) -> String {
+    let mut out = ["kind", "value", "count", "probability"].join(&separator.to_string());
+    out.push('\n');
+    for (kind, value, count, prob) in distribution_rows(results) {
+        out.push_str(&format!("{kind}{separator}{value}{separator}{count}{separator}{prob}\n"));
+    }
+    out
+}
+
+/// The report as Markdown: averages as a list, distributions as pipe tables.
+fn format_markdown(results: &SimResults) -> String {
+    let mut output = String::from("# Monte Carlo Simulation Results\n\n");
+    output.push_str(&format!("- Total games simulated: {}\n", results.total_games));
+    output.push_str(&format!("- Average score: {}\n", format_mean(results.score_stats(), "")));
+    output.push_str(&format!(
+        "- Average length: {}\n\n",
+        format_mean(results.length_stats(), " cards")
+    ));
+
+    let sections = [("Score", &results.score_counts), ("Length", &results.length_counts)];
+    for (i, (label, counts)) in sections.into_iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        output.push_str(&format!("## {} Distribution\n\n", label));
+        if results.total_games == 0 {
+            output.push_str(NO_DATA);
+            output.push('\n');
+        } else {
+            output.push_str(
+                &distribution_table(label, counts, results.total_games).render_markdown(),
+            );
+        }
+    }
+    output
+}
+
+/// The results rendered in a text-based `format`, or `None` for binary formats.
+/// Only the `text` report is ever styled.
+fn format_results(
+    results: &SimResults,
+    format: OutputFormat,
+    caption: Option<&str>,
+    color: bool,
+) -> Option<String> {
+    match format {
+        OutputFormat::Text => Some(format_report(results, color)),
+        OutputFormat::Latex => Some(format_latex(results, caption)),
+        OutputFormat::Json => Some(format_json(results)),
+        OutputFormat::Csv => Some(format_delimited(results, ',')),
+        OutputFormat::Tsv => Some(format_delimited(results, '\t')),
+        OutputFormat::Markdown => Some(format_markdown(results)),
+        OutputFormat::Parquet => None,
+    }
+}
+
+/// Writes the score and length distributions to a Parquet file with columns
+/// `kind` ("score" or "length"), `value`, `count` and `probability`.
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    results: &SimResults,
+    path: &str,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt64Array, UInt8Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+
+    let mut kinds = Vec::new();
+    let mut values = Vec::new();
+    let mut counts = Vec::new();
+    let mut probs = Vec::new();
+    for (kind, value, count, prob) in distribution_rows(results) {
+        kinds.push(kind);
+        values.push(value);
+        counts.push(count);
+        probs.push(prob);
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("value", DataType::UInt8, false),
+        Field::new("count", DataType::UInt64, false),
+        Field::new("probability", DataType::Float64, false),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(kinds)),
+        Arc::new(UInt8Array::from(values)),
+        Arc::new(UInt64Array::from(counts)),
+        Arc::new(Float64Array::from(probs)),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Appends this run to the SQLite archive at `path`: one `runs` row plus one
+/// `distributions` row per observed score and length.
+#[cfg(feature = "sqlite")]
+fn export_sqlite(results: &SimResults, seed: u64, path: &str) -> rusqlite::Result<i64> {
+    let mut conn = rusqlite::Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS runs (
+             id          INTEGER PRIMARY KEY,
+             deck        TEXT NOT NULL,
+             seed        INTEGER,
+             total_games INTEGER NOT NULL,
+             created_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+         );
+         CREATE TABLE IF NOT EXISTS distributions (
+             run_id INTEGER NOT NULL REFERENCES runs(id),
+             kind   TEXT NOT NULL CHECK (kind IN ('score', 'length')),
+             value  INTEGER NOT NULL,
+             count  INTEGER NOT NULL,
+             PRIMARY KEY (run_id, kind, value)
+         );",
+    )?;
+
+    let deck: Vec<String> = results.deck.iter().map(u8::to_string).collect();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (deck, seed, total_games) VALUES (?1, ?2, ?3)",
+        rusqlite::params![deck.join(","), seed as i64, results.total_games as i64],
+    )?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO distributions (run_id, kind, value, count) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let rows = [("score", &results.score_counts), ("length", &results.length_counts)];
+        for (kind, counts) in rows {
+            for (value, count) in counts.iter() {
+                insert.execute(rusqlite::params![run_id, kind, value, count as i64])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(run_id)
+}
+
+/// Handles `--sqlite FILE` at the end of a run. Builds without the `sqlite`
+/// feature reject the flag at startup instead.
+fn export_sqlite_if_requested(results: &SimResults, settings: &ReportSettings) -> Result<()> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &settings.sqlite {
+        let run_id = export_sqlite(results, settings.seed, path)
+            .map_err(|e| Error::Io(format!("Error writing SQLite database: {}", e)))?;
+        if settings.verbosity != Verbosity::Quiet {
+            info!("Run {} archived in SQLite database '{}'", run_id, path);
+        }
+    }
+    #[cfg(not(feature = "sqlite"))]
+    let _ = (results, settings);
+    Ok(())
+}
+
+/// Writes the extra `--save-binary`, `--sqlite` and `--npy` outputs. One
+/// failing does not stop the others.
+fn save_extra_outputs(results: &SimResults, settings: &ReportSettings) -> Result<()> {
+    let quiet = settings.verbosity == Verbosity::Quiet;
+    let mut outcomes = Vec::new();
+    if let Some(path) = &settings.save_binary {
+        outcomes.push(
+            results
+                .save_binary(path)
+                .map(|()| {
+                    if !quiet {
+                        info!("Binary results saved to '{}'", path);
+                    }
+                })
+                .map_err(|e| Error::Io(format!("Error saving binary results: {}", e))),
+        );
+    }
+    outcomes.push(export_sqlite_if_requested(results, settings));
+    if let Some(path) = &settings.npy {
+        outcomes.push(
+            File::create(path)
+                .and_then(|file| npy::write_f64_array(BufWriter::new(file), &results.score_pmf()))
+                .map(|()| {
+                    if !quiet {
+                        info!("Score PMF saved to '{}'", path);
+                    }
+                })
+                .map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e))),
+        );
+    }
+    first_error(outcomes)
+}
+
+/// Games, averages and standard deviations on a single line.
+fn summary_line(results: &SimResults) -> String {
+    let (Some((avg_score, sd_score)), Some((avg_length, sd_length))) =
+        (results.score_stats(), results.length_stats())
+    else {
+        return "games=0".to_string();
+    };
+    format!(
+        "games={} avg_score={:.4} sd_score={:.4} avg_length={:.4} sd_length={:.4}",
+        results.total_games, avg_score, sd_score, avg_length, sd_length
+    )
+}
+
+/// Prints the `summary_line`.
+pub fn print_summary_line(results: &SimResults) {
+    println!("{}", summary_line(results));
+}
+
+/// Logs the running statistics shown at each interval in verbose mode.
+fn log_interval_details(results: &SimResults) {
+    let (Some((avg_score, sd_score)), Some((avg_length, sd_length))) =
+        (results.score_stats(), results.length_stats())
+    else {
+        return;
+    };
+    let score_mode = results.top_values(&results.score_counts, 1)[0].0;
+    let length_mode = results.top_values(&results.length_counts, 1)[0].0;
+    info!(
+        "    score {:.4} ± {:.4} (mode {}) | length {:.4} ± {:.4} (mode {})",
+        avg_score, sd_score, score_mode, avg_length, sd_length, length_mode
+    );
+
+    let top: Vec<String> = results
+        .top_values(&results.score_counts, 5)
+        .iter()
+        .map(|(score, prob)| format!("{} {:.2}%", score, prob * 100.0))
+        .collect();
+    info!("    top scores: {}", top.join(", "));
+}
+
+/// Writes the results to the output file in the chosen format.
+fn save_results(results: &SimResults, settings: &ReportSettings) -> Result<()> {
+    let path = settings.output.as_str();
+    let caption = settings.caption.as_deref();
+    let written: std::result::Result<(), Box<dyn std::error::Error>> = match settings.format {
+        OutputFormat::Text
+        | OutputFormat::Latex
+        | OutputFormat::Json
+        | OutputFormat::Csv
+        | OutputFormat::Tsv
+        | OutputFormat::Markdown => {
+            let text = format_results(results, settings.format, caption, false)
+                .expect("text-based format");
+            File::create(path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .map_err(Into::into)
+        }
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => write_parquet(results, path),
+        #[cfg(not(feature = "parquet"))]
+        OutputFormat::Parquet => unreachable!("rejected at startup without the parquet feature"),
+    };
+    written.map_err(|e| Error::Io(format!("Error writing '{}': {}", path, e)))?;
+    if settings.verbosity != Verbosity::Quiet {
+        info!("Results successfully saved to '{}'", path);
+    }
+    Ok(())
+}
+
+/// `merge FILE... [--save-binary OUT]`: sums saved binary results and
+/// reports them as a single sample.
+pub fn run_merge(paths: &[String], save_binary: Option<&str>, color: bool) -> Result<()> {
+    let mut merged: Option<SimResults> = None;
+    for path in paths {
+        let results = load_or_fail(path)?;
+        info!("Loaded '{}' ({} games).", path, results.total_games);
+        match &mut merged {
+            None => merged = Some(results),
+            Some(total) => {
+                total
+                    .merge(&results)
+                    .map_err(|e| Error::Invalid(format!("Cannot merge '{}': {}", path, e)))?;
+            }
+        }
+    }
+
+    let merged = merged.expect("at least one file was loaded");
+    println!("{}", format_report(&merged, color));
+    if let Some(path) = save_binary {
+        merged
+            .save_binary(path)
+            .map_err(|e| Error::Io(format!("Error saving binary results: {}", e)))?;
+        info!("Merged results saved to '{}'", path);
+    }
+    Ok(())
+}
+
+/// Loads a binary results file, describing any failure with its path.
+fn load_or_fail(path: &str) -> Result<SimResults> {
+    SimResults::load_binary(path).map_err(|e| Error::Io(format!("Error loading '{}': {}", path, e)))
+}
+
+/// Default `--tolerance` for `diff`, as an absolute probability.
+pub const DEFAULT_DIFF_TOLERANCE: f64 = 0.001;
+
+/// Rows of `label` values whose probabilities in `a` and `b` differ by more
+/// than `tolerance`, as a table of both probabilities and their difference.
+fn diff_table(
+    label: &str,
+    a: (&Counts, u64),
+    b: (&Counts, u64),
+    tolerance: f64,
+) -> Option<Table> {
+    let mut values: Vec<u8> = a.0.iter().chain(b.0.iter()).map(|(v, _)| v).collect();
+    values.sort_unstable();
+    values.dedup();
+
+    let prob = |(counts, total): (&Counts, u64), v: u8| counts.get(v) as f64 / total as f64;
+
+    let mut table = Table::new(&[
+        (label, Align::Right),
+        ("A", Align::Right),
+        ("B", Align::Right),
+        ("B - A", Align::Right),
+    ]);
+    let mut any = false;
+    for v in values {
+        let (pa, pb) = (prob(a, v), prob(b, v));
+        if (pb - pa).abs() > tolerance {
+            any = true;
+            table.add_row(vec![
+                v.to_string(),
+                format!("{:.6}%", pa * 100.0),
+                format!("{:.6}%", pb * 100.0),
+                format!("{:+.6}%", (pb - pa) * 100.0),
+            ]);
+        }
+    }
+    any.then_some(table)
+}
+
+/// `diff A B [--tolerance T]`: compares two saved results and lists the
+/// score and length probabilities that differ by more than `T` (an absolute
+/// probability, so 0.001 is a tenth of a percentage point).
+pub fn run_diff(path_a: &str, path_b: &str, tolerance: f64, color: bool) -> Result<()> {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(Error::Invalid(format!(
+            "Invalid --tolerance {}: expected a non-negative number",
+            tolerance
+        )));
+    }
+
+    let (a, b) = (load_or_fail(path_a)?, load_or_fail(path_b)?);
+    if a.total_games == 0 || b.total_games == 0 {
+        return Err(Error::Invalid(
+            "Cannot compare: one of the files holds no games".to_string(),
+        ));
+    }
+    if a.deck != b.deck {
+        warn!("The two files were played with different decks.");
+    }
+
+    println!("A: '{}' ({} games)", path_a, a.total_games);
+    println!("B: '{}' ({} games)", path_b, b.total_games);
+    println!("Tolerance: {}\n", tolerance);
+
+    // Neither file is empty, so both have averages.
+    let mean = |stats: Option<(f64, f64)>| stats.expect("games were played").0;
+    let (a_score, b_score) = (mean(a.score_stats()), mean(b.score_stats()));
+    let (a_length, b_length) = (mean(a.length_stats()), mean(b.length_stats()));
+    println!("{}", color::bold("--- Averages ---", color));
+    println!("Average Score:  {:.4} vs {:.4} ({:+.4})", a_score, b_score, b_score - a_score);
+    println!("Average Length: {:.4} vs {:.4} ({:+.4})", a_length, b_length, b_length - a_length);
+
+    let sections = [
+        ("Score", &a.score_counts, &b.score_counts),
+        ("Length", &a.length_counts, &b.length_counts),
+    ];
+    for (label, a_counts, b_counts) in sections {
+        println!("\n{}", color::bold(&format!("--- {} Differences ---", label), color));
+        match diff_table(label, (a_counts, a.total_games), (b_counts, b.total_games), tolerance) {
+            Some(table) => print!("{}", table.render_styled(color)),
+            None => println!("None above tolerance."),
+        }
+    }
+    Ok(())
+}
+
+/// Games a worker plays between merges into the shared results. Smaller
+/// batches show progress sooner; larger ones contend less on the lock.
+const WORKER_BATCH: u64 = 10_000;
+
+/// Default of `--rng-batch`: no extra buffering. `StdRng` already generates
+/// a ChaCha block at a time, and copying its output through a second buffer
+/// measured about 10% slower end to end.
+const DEFAULT_RNG_BATCH: usize = 0;
+
+/// The seed of worker `index`. Worker 0 uses `seed` itself, so a run with a
+/// single thread reproduces the serial game sequence exactly. With fixed
+/// shares of the games, the same seed and thread count replay a run.
+fn worker_seed(seed: u64, index: u64) -> u64 {
+    seed.wrapping_add(index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Games worker `index` of `threads` plays of a run of `games`: an even
+/// share, the first `games % threads` workers taking one more.
+fn worker_quota(games: u64, threads: u64, index: u64) -> u64 {
+    games / threads + u64::from(index < games % threads)
+}
+
+/// Locks `mutex` even if a thread panicked while holding it, keeping the
+/// data rather than losing everything gathered so far with that thread.
+/// The lock is cleared so the warning, naming `what` it guards, shows once.
+fn lock_or_recover<'a, T>(mutex: &'a Mutex<T>, what: &str) -> MutexGuard<'a, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        warn!("A thread panicked while updating the {}; carrying on with them as they are", what);
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// The data of a `Mutex` whose users are done, recovered as in
+/// `lock_or_recover` if one of them panicked.
+fn into_inner_or_recover<T>(mutex: Mutex<T>, what: &str) -> T {
+    mutex.into_inner().unwrap_or_else(|poisoned: PoisonError<T>| {
+        warn!("A thread panicked while updating the {}; carrying on with them as they are", what);
+        poisoned.into_inner()
+    })
+}
+
+/// State shared by all simulation workers.
+struct Workers<'a> {
+    results: &'a Mutex<SimResults>,
+    ndjson: Option<&'a NdjsonSink>,
+    /// Cleared by the first worker whose NDJSON write fails.
+    ndjson_ok: &'a AtomicBool,
+    /// Set by Ctrl+C: every worker finishes the game in hand, merges what
+    /// it has played and returns.
+    stop: &'a AtomicBool,
+    games: Option<u64>,
+    rule: &'a dyn StoppingRule,
+    /// How the jokers in the deck score.
+    joker: JokerValue,
+    /// How the aces count, if soft.
+    soft: Option<SoftAces>,
+    /// Draw weight of each card by its points, if the draw is weighted.
+    weights: Option<PointWeights>,
+    verbosity: Verbosity,
+    start_time: Instant,
+    /// Words of each worker's generator buffered at a time; 0 for none.
+    rng_batch: usize,
+}
+
+impl Workers<'_> {
+    /// Plays `quota` games (or forever without one) until `stop` is set,
+    /// merging into the shared results every `WORKER_BATCH` games and once
+    /// more on the way out. Returns how many games it played.
+    fn run<R: Rng>(&self, mut deck: Vec<u8>, mut rng: R, quota: Option<u64>) -> u64 {
+        let mut batch = lock_or_recover(self.results, "results").emptied();
+        let mut records = String::new();
+        let weights = self.weights.as_ref();
+        let mut played = 0;
+        loop {
+            if self.stop.load(Ordering::Relaxed) || quota.is_some_and(|q| played == q) {
+                break;
+            }
+            played += 1;
+            let (final_score, game_length) =
+                play_game(&mut deck, self.rule, self.joker, self.soft, weights, &mut rng);
+            batch.record(final_score, game_length);
+            if self.ndjson.is_some() {
+                records.push_str(&format!(
+                    "{{\"score\":{},\"length\":{}}}\n",
+                    final_score, game_length
+                ));
+            }
+            if batch.total_games == WORKER_BATCH {
+                self.flush(&mut batch, &mut records);
+            }
+        }
+        self.flush(&mut batch, &mut records);
+        played
+    }
+
+    /// Runs `threads` workers to completion, worker `i` seeded with
+    /// `worker_seed(seed, i)` and playing `worker_quota` of the games. Each
+    /// worker's share is fixed up front rather than claimed as it goes, so
+    /// a seed and thread count always play the same games. A worker that
+    /// panics loses only the games it had not merged yet; the others play
+    /// on and the run is reported. Returns the games the workers that
+    /// finished played, counted apart from the results they merged into.
+    fn play(&self, deck: &[u8], seed: u64, threads: u64) -> u64 {
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|index| {
+                    let rng = StdRng::seed_from_u64(worker_seed(seed, index));
+                    let quota = self.games.map(|games| worker_quota(games, threads, index));
+                    let deck = deck.to_vec();
+                    match self.rng_batch {
+                        0 => scope.spawn(move || self.run(deck, rng, quota)),
+                        batch => scope
+                            .spawn(move || self.run(deck, BatchRng::new(rng, batch), quota)),
+                    }
+                })
+                .collect();
+            // Joined here, a panic is reported instead of rethrown by the scope.
+            let mut played = 0;
+            for (index, worker) in workers.into_iter().enumerate() {
+                match worker.join() {
+                    Ok(games) => played += games,
+                    Err(_) => error!("Worker {} panicked; the games it had not merged are lost", index),
+                }
+            }
+            played
+        })
+    }
+
+    /// Merges a batch into the shared results, then streams its records.
+    fn flush(&self, batch: &mut SimResults, records: &mut String) {
+        if batch.total_games == 0 {
+            return;
+        }
+        // Only the merge happens under the lock; what the progress line needs
+        // is copied out and reported after the lock is released.
+        let progress = {
+            let mut results = lock_or_recover(self.results, "results");
+            let before = results.total_games;
+            results.merge(batch).expect("workers play the same deck and rules");
+
+            // Provide periodic updates to the user without slowing down too much.
+            let crossed = results.total_games / 1_000_000 > before / 1_000_000;
+            (self.verbosity != Verbosity::Quiet && crossed).then(|| {
+                let details = (self.verbosity == Verbosity::Verbose).then(|| results.clone());
+                (results.total_games, details)
+            })
+        };
+        if let Some((total_games, details)) = progress {
+            let elapsed = self.start_time.elapsed().as_secs_f64();
+            let games_per_sec = total_games as f64 / elapsed;
+            info!(
+                "Games played: {:>10} ({:.2} million games/sec)",
+                total_games,
+                games_per_sec / 1_000_000.0
+            );
+            if let Some(snapshot) = details {
+                log_interval_details(&snapshot);
+            }
+        }
+        *batch = batch.emptied();
+
+        // Written after the results lock is released, so one worker's slow
+        // write does not hold up the others' merges.
+        if let Some(sink) = self.ndjson {
+            if self.ndjson_ok.load(Ordering::Relaxed) {
+                let mut sink = lock_or_recover(sink, "NDJSON stream");
+                if let Err(e) = sink.write_records(records) {
+                    if self.ndjson_ok.swap(false, Ordering::Relaxed) {
+                        error!("Error writing NDJSON record, streaming stopped: {}", e);
+                    }
+                }
+            }
+        }
+        records.clear();
+    }
+}
+
+/// Plays exactly `games` games without any console output or Ctrl+C
+/// handling, for callers that only need the counts.
+pub fn simulate(
+    deck: &Deck,
+    rule: &dyn StoppingRule,
+    games: u64,
+    seed: u64,
+    threads: u64,
+) -> SimResults {
+    let cards = deck.cards();
+    let results = Mutex::new(SimResults::new(deck, rule));
+    let workers = Workers {
+        results: &results,
+        ndjson: None,
+        ndjson_ok: &AtomicBool::new(false),
+        stop: &AtomicBool::new(false),
+        games: Some(games),
+        rule,
+        joker: deck.joker_value(),
+        soft: SoftAces::of(deck, rule),
+        weights: draw_weights(deck),
+        verbosity: Verbosity::Quiet,
+        start_time: Instant::now(),
+        rng_batch: DEFAULT_RNG_BATCH,
+    };
+    workers.play(&cards, seed, threads);
+    into_inner_or_recover(results, "results")
+}
+
+/// Games per thread count of `--bench-threads` without `--games`.
+const BENCH_GAMES: u64 = 4_000_000;
+
+/// Thread counts `--bench-threads` tries: the powers of two below
+/// `max_threads`, then `max_threads` itself.
+fn bench_thread_counts(max_threads: u64) -> Vec<u64> {
+    let mut counts: Vec<u64> = (0..)
+        .map(|i| 1u64 << i)
+        .take_while(|&n| n < max_threads)
+        .collect();
+    counts.push(max_threads);
+    counts
+}
+
+/// `--bench-threads`: plays the same games at each thread count and prints
+/// the throughput and speedup over one thread. A speedup well below the
+/// thread count, on a machine with that many idle cores, points at
+/// contention between the workers.
+fn bench_threads(deck: &Deck, rule: &dyn StoppingRule, games: u64, seed: u64, max_threads: u64) {
+    info!(
+        "Timing {} games at up to {} threads ({} CPUs available).",
+        games,
+        max_threads,
+        default_threads()
+    );
+    let mut table = Table::new(&[
+        ("Threads", Align::Right),
+        ("Games/s", Align::Right),
+        ("Speedup", Align::Right),
+    ]);
+    let mut single_rate = None;
+    for threads in bench_thread_counts(max_threads) {
+        let start = Instant::now();
+        simulate(deck, rule, games, seed, threads);
+        let rate = games as f64 / start.elapsed().as_secs_f64();
+        let single = *single_rate.get_or_insert(rate);
+        table.add_row(vec![
+            threads.to_string(),
+            format!("{:.0}", rate),
+            format!("{:.2}x", rate / single),
+        ]);
+    }
+    print!("{}", table.render());
+}
+
+/// One worker per CPU, the default of `--threads`.
+pub fn default_threads() -> u64 {
+    thread::available_parallelism().map_or(1, |n| n.get() as u64)
+}
+
+/// `--dry-run`: lists every setting the run would use, after the command
+/// line, environment and config file are merged and defaults filled in.
+fn print_dry_run(
+    args: &RunArgs,
+    deck: &Deck,
+    threshold: u8,
+    format: OutputFormat,
+    threads: u64,
+    output: &str,
+) {
+    let format_name = format.to_possible_value().expect("no skipped variants");
+    let mut table = Table::new(&[("Setting", Align::Left), ("Value", Align::Left)]);
+    let mut add = |name: &str, value: String| table.add_row(vec![name.to_string(), value]);
+    add("config", args.config.clone().unwrap_or_else(|| "none".to_string()));
+    add("deck", format!("{} ({} cards)", deck, deck.len()));
+    if deck.values()[..deck.ranks()] != DEFAULT_VALUES[..deck.ranks()] {
+        let values: Vec<String> =
+            deck.values()[..deck.ranks()].iter().map(u8::to_string).collect();
+        add("values", values.join(","));
+    }
+    if deck.is_weighted() {
+        let weights: Vec<String> =
+            deck.weights()[..deck.ranks()].iter().map(f64::to_string).collect();
+        add("weights", weights.join(","));
+    }
+    add("threshold", threshold.to_string());
+    let aces = match (args.aces_high, args.soft_aces) {
+        (true, _) => format!("high ({} points)", ACE_HIGH),
+        (false, Some(bust)) => format!("soft, busting past {}", bust),
+        (false, None) => format!("{} points", deck.values()[0]),
+    };
+    add("aces", aces);
+    let jokers = match (deck.jokers(), deck.joker_value()) {
+        (0, _) => "none".to_string(),
+        (n, JokerValue::Best) => format!("{} scoring best", n),
+        (n, JokerValue::Fixed(points)) => format!("{} scoring {}", n, points),
+    };
+    add("jokers", jokers);
+    let removed = match &args.remove {
+        Some(removed) if removed.values().any(|&count| count > 0) => removed
+            .iter()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(rank, count)| format!("{}={}", rank, count))
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => "none".to_string(),
+    };
+    add("remove", removed);
+    add("games", args.games.map_or("until Ctrl+C".to_string(), |g| g.to_string()));
+    add("seed", args.seed.map_or("random".to_string(), |s| s.to_string()));
+    add("threads", threads.to_string());
+    add("rng-batch", match args.rng_batch.map_or(DEFAULT_RNG_BATCH, |b| b as usize) {
+        0 => "none".to_string(),
+        words => format!("{} words", words),
+    });
+    add("format", format_name.get_name().to_string());
+    add("output", output.to_string());
+    let optional = [
+        ("resume", &args.resume),
+        ("ndjson", &args.ndjson),
+        ("save-binary", &args.save_binary),
+        ("sqlite", &args.sqlite),
+        ("npy", &args.npy),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            add(name, value.clone());
+        }
+    }
+    print!("{}", table.render());
+}
+
+/// `sim`: plays games until Ctrl+C (or `--games`), then reports the score
+/// and length distributions and writes the requested outputs.
+pub fn run(mut args: RunArgs, color: bool) -> Result<()> {
+    // A variant stands in for the options it sets, ahead of any config.
+    if let Some(variant) = args.variant.take() {
+        args.deck = Some(variant.deck);
+        args.threshold = Some(variant.threshold);
+        args.soft_aces = args.soft_aces.or(variant.soft_aces);
+    }
+    if let Some(path) = &args.config {
+        args.apply_config(RunConfig::load(path)?);
+    }
+    // Clap keeps --soft-aces from --aces-high, but not aces set here.
+    if args.aces_high && args.soft_aces.is_some() {
+        return Err(Error::Invalid(
+            "--aces-high conflicts with the soft aces of the variant or config file".to_string(),
+        ));
+    }
+    let threshold = args.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    let format = args.format.unwrap_or(OutputFormat::Text);
+    let deck_spec = args
+        .deck
+        .take()
+        .unwrap_or_else(|| Deck::preset("spanish-40").expect("built-in preset"));
+    let deck_spec = match args.values {
+        Some(values) => deck_spec.with_values(values),
+        None => deck_spec,
+    };
+    let deck_spec = if args.aces_high { deck_spec.with_ace_value(ACE_HIGH) } else { deck_spec };
+    let soft_reach = args
+        .soft_aces
+        .map(|bust| rule::soft_reach(bust, threshold.into()))
+        .transpose()
+        .map_err(|e| Error::Invalid(format!("--soft-aces: {}", e)))?;
+    let deck_spec = deck_spec
+        .with_jokers(args.jokers.unwrap_or(0), args.joker_value.unwrap_or(JokerValue::Best))
+        .with_soft_aces(soft_reach)
+        .with_weights(args.weights.unwrap_or(deck::FAIR_WEIGHTS));
+    let deck_spec = match &args.remove {
+        Some(removed) => deck_spec
+            .without(removed)
+            .map_err(|e| Error::Invalid(format!("--remove: {}", e)))?,
+        None => deck_spec,
+    };
+
+    if cfg!(not(feature = "parquet")) && format == OutputFormat::Parquet {
+        return Err(Error::Invalid(
+            "--format parquet needs a build with the `parquet` feature enabled".to_string(),
+        ));
+    }
+    if cfg!(not(feature = "sqlite")) && args.sqlite.is_some() {
+        return Err(Error::Invalid(
+            "--sqlite needs a build with the `sqlite` feature enabled".to_string(),
+        ));
+    }
+    if deck_spec.is_empty() {
+        return Err(Error::Invalid("The deck has no cards".to_string()));
+    }
+    let rule = SumThreshold(threshold.into());
+    check_game_width(&deck_spec, &rule)?;
+    if rule::always_runs_out(&rule, &deck_spec) {
+        warn!("{}", crate::exact::runs_out_message(&deck_spec, threshold.into()));
+    }
+    let threads = args.threads.unwrap_or_else(default_threads);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| format.default_path().to_string());
+
+    if args.dry_run {
+        print_dry_run(&args, &deck_spec, threshold, format, threads, &output);
+        return Ok(());
+    }
+    if args.bench_threads {
+        let games = args.games.unwrap_or(BENCH_GAMES);
+        if games == 0 {
+            return Err(Error::Invalid("--bench-threads needs at least one game".to_string()));
+        }
+        bench_threads(&deck_spec, &rule, games, args.seed.unwrap_or(0), threads);
+        return Ok(());
+    }
+
+    let verbosity = if args.quiet {
+        Verbosity::Quiet
+    } else if args.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    };
+
+    let deck = deck_spec.cards();
+
+    // `--resume FILE` continues counting on top of a saved binary snapshot.
+    let fresh = SimResults::new(&deck_spec, &rule);
+    let initial_results = match &args.resume {
+        Some(path) => {
+            let results = load_or_fail(path)?;
+            fresh
+                .check_compatible(&results)
+                .map_err(|e| Error::Invalid(format!("Cannot resume '{}': {}", path, e)))?;
+            if verbosity != Verbosity::Quiet {
+                info!("Resuming from '{}' ({} games).", path, results.total_games);
+            }
+            results
+        }
+        None => fresh,
+    };
+
+    // Without --seed one is drawn at random and logged, so any run can be repeated.
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.seed.is_none() {
+        if verbosity == Verbosity::Quiet {
+            info!("seed={}", seed);
+        } else {
+            info!(
+                "Using random seed {} (pass --seed {} --threads {} to reproduce this run).",
+                seed, seed, threads
+            );
+        }
+    }
+
+    let settings = ReportSettings {
+        color,
+        console: args.ndjson.as_deref() != Some("-"),
+        verbosity,
+        format,
+        output,
+        caption: args.caption.clone(),
+        seed,
+        save_binary: args.save_binary.clone(),
+        sqlite: args.sqlite.clone(),
+        npy: args.npy.clone(),
+    };
+
+    // The shared results, merged into by every worker.
+    let results_data = Mutex::new(initial_results);
+
+    // Optional firehose of one JSON object per completed game.
+    let ndjson = args.ndjson.as_deref().map(open_ndjson_sink).transpose()?;
+    let ndjson_ok = AtomicBool::new(true);
+
+    // Ctrl+C only asks the workers to stop: each merges the games it has
+    // finished and the run is reported below, once, so the report and the
+    // NDJSON stream both hold every completed game. A second Ctrl+C gives
+    // up on them and exits at once.
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || {
+        if handler_stop.swap(true, Ordering::Relaxed) {
+            error!("Interrupted again, exiting without a report");
+            std::process::exit(Error::Aborted(String::new()).exit_code());
+        }
+        info!("Stopping: finishing the games in progress (Ctrl+C again to quit at once)");
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    if verbosity != Verbosity::Quiet {
+        match args.games {
+            Some(games) => info!("Starting simulation of {} games... Press Ctrl+C to stop early.", games),
+            None => info!("Starting simulation... Press Ctrl+C to stop and save results."),
+        }
+    }
+
+    let workers = Workers {
+        results: &results_data,
+        ndjson: ndjson.as_ref(),
+        ndjson_ok: &ndjson_ok,
+        stop: &stop,
+        games: args.games,
+        rule: &rule,
+        joker: deck_spec.joker_value(),
+        soft: SoftAces::of(&deck_spec, &rule),
+        weights: draw_weights(&deck_spec),
+        verbosity,
+        start_time: Instant::now(),
+        rng_batch: args.rng_batch.map_or(DEFAULT_RNG_BATCH, |b| b as usize),
+    };
+
+    // The simulation proper. Without --games the workers run until interrupted.
+    workers.play(&deck, seed, threads);
+
+    // Every worker has returned, so the results hold each game exactly once.
+    let interrupted = stop.load(Ordering::Relaxed);
+    let results = into_inner_or_recover(results_data, "results");
+    if let Some(sink) = &ndjson {
+        let _ = lock_or_recover(sink, "NDJSON stream").flush();
+    }
+    finish(&results, &settings, &ndjson_ok, interrupted)?;
+    match args.games {
+        // Without --games, Ctrl+C is the normal way to end a run.
+        Some(games) if interrupted => Err(Error::Aborted(format!(
+            "Interrupted after {} of {} games",
+            results.total_games, games
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Reports and writes every output at the end of a run, whether it ended
+/// normally or by Ctrl+C. Every output is attempted; the first error wins.
+fn finish(
+    results: &SimResults,
+    settings: &ReportSettings,
+    ndjson_ok: &AtomicBool,
+    interrupted: bool,
+) -> Result<()> {
+    let saved = report_and_save_results(results, settings, interrupted);
+    let extras = save_extra_outputs(results, settings);
+    let streamed = if ndjson_ok.load(Ordering::Relaxed) {
+        Ok(())
+    } else {
+        Err(Error::Io("The NDJSON stream stopped early".to_string()))
+    };
+    first_error([saved, extras, streamed])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quiet workers playing `rule` on the shared `results`, stopped by
+    /// `stop` and, if `games` is given, after that many games.
+    fn workers<'a>(
+        results: &'a Mutex<SimResults>,
+        stop: &'a AtomicBool,
+        games: Option<u64>,
+        rule: &'a SumThreshold,
+    ) -> Workers<'a> {
+        static NDJSON_OK: AtomicBool = AtomicBool::new(true);
+        Workers {
+            results,
+            ndjson: None,
+            ndjson_ok: &NDJSON_OK,
+            stop,
+            games,
+            rule,
+            joker: JokerValue::Best,
+            soft: None,
+            weights: None,
+            verbosity: Verbosity::Quiet,
+            start_time: Instant::now(),
+            rng_batch: DEFAULT_RNG_BATCH,
+        }
+    }
+
+    /// Every game counted once: in the total, in the scores and in the lengths.
+    fn assert_consistent(results: &SimResults) {
+        let scores: u64 = results.score_counts.iter().map(|(_, count)| count).sum();
+        let lengths: u64 = results.length_counts.iter().map(|(_, count)| count).sum();
+        assert_eq!(results.total_games, scores);
+        assert_eq!(results.total_games, lengths);
+    }
+
+    fn spanish_40() -> Deck {
+        Deck::preset("spanish-40").expect("built-in preset")
+    }
+
+    #[test]
+    fn no_games_report_no_data() {
+        let results = SimResults::new(&spanish_40(), &SumThreshold::default());
+        assert_eq!(summary_line(&results), "games=0");
+        assert!(format_report(&results, false).contains(NO_DATA));
+        for &format in OutputFormat::value_variants() {
+            if let Some(report) = format_results(&results, format, None, false) {
+                assert!(!report.contains("NaN"), "{}", report);
+            }
+        }
+    }
+
+    #[test]
+    fn results_of_other_rules_do_not_merge() {
+        let deck = spanish_40();
+        let classic = SimResults::new(&deck, &SumThreshold(30));
+        let mut weights = deck::FAIR_WEIGHTS;
+        weights[deck::TENS] = 1.05;
+        let others = [
+            SimResults::new(&deck, &SumThreshold(21)),
+            SimResults::new(&deck.clone().with_soft_aces(Some(5)), &SumThreshold(30)),
+            SimResults::new(&deck.clone().with_weights(weights), &SumThreshold(30)),
+        ];
+        for other in &others {
+            let err = classic.clone().merge(other).expect_err("other rules must not merge");
+            assert!(err.starts_with("incompatible rules"), "{}", err);
+        }
+        assert!(classic.clone().merge(&SimResults::new(&deck, &SumThreshold(30))).is_ok());
+    }
+
+    #[test]
+    fn binary_files_load_what_was_saved() {
+        // A blackjack dealer's rules, so the header has some to keep.
+        let (deck, rule) = (spanish_40().with_soft_aces(Some(5)), SumThreshold(16));
+        let results = simulate(&deck, &rule, 1000, 3, 2);
+        let path = std::env::temp_dir().join(format!("teqr-saved-{}.bin", std::process::id()));
+        let path = path.to_str().expect("a UTF-8 temp path");
+        results.save_binary(path).expect("the file saves");
+        let loaded = SimResults::load_binary(path);
+        let _ = std::fs::remove_file(path);
+        let loaded = loaded.expect("the file loads");
+        assert_eq!(loaded.deck, results.deck);
+        assert_eq!(loaded.rules, results.rules);
+        assert_eq!(loaded.score_counts, results.score_counts);
+        assert_eq!(loaded.length_counts, results.length_counts);
+        assert_eq!(loaded.total_games, 1000);
+    }
+
+    #[test]
+    fn stop_before_the_first_game_plays_none() {
+        let (deck, rule) = (spanish_40(), SumThreshold::default());
+        let results = Mutex::new(SimResults::new(&deck, &rule));
+        let stop = AtomicBool::new(true);
+        let played = workers(&results, &stop, Some(50_000), &rule).play(&deck.cards(), 1, 4);
+        let results = results.into_inner().unwrap();
+        assert_eq!(played, 0);
+        assert_eq!(results.total_games, 0);
+        assert_consistent(&results);
+    }
+
+    #[test]
+    fn stop_mid_run_keeps_every_finished_game() {
+        let (deck, rule) = (spanish_40(), SumThreshold::default());
+        let results = Mutex::new(SimResults::new(&deck, &rule));
+        let stop = AtomicBool::new(false);
+        let workers = workers(&results, &stop, None, &rule);
+        let played = thread::scope(|scope| {
+            scope.spawn(|| {
+                // Stop once a few batches are in, as Ctrl+C would.
+                while lock_or_recover(&results, "results").total_games < 3 * WORKER_BATCH {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                stop.store(true, Ordering::Relaxed);
+            });
+            workers.play(&deck.cards(), 7, 4)
+        });
+        let results = results.into_inner().unwrap();
+        assert!(results.total_games >= 3 * WORKER_BATCH);
+        // Counted by the workers as they play, not from the merged results.
+        assert_eq!(results.total_games, played);
+        assert_consistent(&results);
+    }
+
+    #[test]
+    fn same_seed_and_threads_replay_a_run() {
+        let (deck, rule) = (spanish_40(), SumThreshold::default());
+        let games = 5 * WORKER_BATCH + 3;
+        let first = simulate(&deck, &rule, games, 11, 4);
+        let second = simulate(&deck, &rule, games, 11, 4);
+        assert_eq!(first.total_games, games);
+        assert_eq!(first.score_counts, second.score_counts);
+        assert_eq!(first.length_counts, second.length_counts);
+        assert_eq!(first.total_games, second.total_games);
+    }
+
+    #[test]
+    fn bounded_run_plays_exactly_its_games() {
+        let (deck, rule) = (spanish_40(), SumThreshold::default());
+        let results = Mutex::new(SimResults::new(&deck, &rule));
+        let stop = AtomicBool::new(false);
+        let games = 3 * WORKER_BATCH + 17;
+        let played = workers(&results, &stop, Some(games), &rule).play(&deck.cards(), 7, 4);
+        let results = results.into_inner().unwrap();
+        assert_eq!(played, games);
+        assert_eq!(results.total_games, games);
+        assert_consistent(&results);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::color;
+
+/// How the cells of one column are padded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// A plain-text table whose column widths are computed from its contents,
+/// so values of different digit counts still line up.
+pub struct Table {
+    headers: Vec<String>,
+    aligns: Vec<Align>,
+    rows: Vec<Vec<String>>,
+    highlighted: Option<usize>,
+}
+
+impl Table {
+    /// Creates a table with one `(header, alignment)` pair per column.
+    pub fn new(columns: &[(&str, Align)]) -> Self {
+        Table {
+            headers: columns.iter().map(|(h, _)| h.to_string()).collect(),
+            aligns: columns.iter().map(|&(_, a)| a).collect(),
+            rows: Vec::new(),
+            highlighted: None,
+        }
+    }
+
+    /// Appends a row. Missing trailing cells render as blanks.
+    pub fn add_row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    /// Marks the row at `index` to be highlighted in styled output.
+    pub fn highlight_row(&mut self, index: usize) {
+        self.highlighted = Some(index);
+    }
+
+    /// Width of each column: the longest of its header and cells.
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate().take(widths.len()) {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+        widths
+    }
+
+    fn render_line(&self, cells: &[String], widths: &[usize]) -> String {
+        let padded: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| {
+                let cell = cells.get(i).map(String::as_str).unwrap_or("");
+                match self.aligns[i] {
+                    Align::Left => format!("{:<w$}", cell),
+                    Align::Right => format!("{:>w$}", cell),
+                }
+            })
+            .collect();
+        padded.join(" | ").trim_end().to_string()
+    }
+
+    /// Renders the header, a rule, and every row, each ending in a newline.
+    pub fn render(&self) -> String {
+        self.render_styled(false)
+    }
+
+    /// Like `render`, but with a bold header and the highlighted row in
+    /// color when `color` is set. Padding is applied before styling so the
+    /// escape codes never affect alignment.
+    pub fn render_styled(&self, color: bool) -> String {
+        let widths = self.widths();
+        let mut out = color::bold(&self.render_line(&self.headers, &widths), color);
+        out.push('\n');
+
+        let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+        out.push_str(&rule.join("-+-"));
+        out.push('\n');
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let line = self.render_line(row, &widths);
+            if self.highlighted == Some(i) {
+                out.push_str(&color::highlight(&line, color));
+            } else {
+                out.push_str(&line);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders a GitHub-flavored Markdown pipe table. Pipes inside cells are
+    /// escaped; highlighting is not shown.
+    pub fn render_markdown(&self) -> String {
+        let escape = |cell: &str| cell.replace('|', "\\|");
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+        let mut out = line(self.headers.iter().map(|h| escape(h)).collect());
+        out.push_str(&line(
+            self.aligns
+                .iter()
+                .map(|a| match a {
+                    Align::Left => ":---".to_string(),
+                    Align::Right => "---:".to_string(),
+                })
+                .collect(),
+        ));
+        for row in &self.rows {
+            let cells = (0..self.headers.len())
+                .map(|i| escape(row.get(i).map(String::as_str).unwrap_or("")))
+                .collect();
+            out.push_str(&line(cells));
+        }
+        out
+    }
+}
+
+/// Index of the largest value, used to pick the row to highlight.
+pub fn argmax<T: PartialOrd>(values: impl IntoIterator<Item = T>) -> Option<usize> {
+    let mut best: Option<(usize, T)> = None;
+    for (i, v) in values.into_iter().enumerate() {
+        if best.as_ref().is_none_or(|(_, b)| v > *b) {
+            best = Some((i, v));
+        }
+    }
+    best.map(|(i, _)| i)
+}
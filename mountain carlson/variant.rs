@@ -0,0 +1,51 @@
+//! Named variants of the game: a deck together with the rules its rows are
+//! drawn by, so `--variant` can stand in for the options that set them.
+//! The `exact`, `sim` and `compare` subcommands take a variant; the others
+//! take their deck and threshold as options.
+
+use crate::deck::Deck;
+use crate::rule::DEFAULT_THRESHOLD;
+
+/// Built-in variants, with a short description each. The first three are
+/// the games the solver files next to this folder were written for.
+pub const VARIANTS: &[(&str, &str)] = &[
+    ("classic-6deck", "six-deck shoe drawing past 30, the game of 512.rs"),
+    ("spanish-40", "spanish-40 deck drawing past 30, the game of memo_sim.rs"),
+    ("simplified-40", "standard-40 deck drawing past 30, the game of 30_b_w.rs"),
+    ("blackjack-21", "standard-52 deck drawing to 17, the aces soft and busting past 21"),
+];
+
+/// A deck and the threshold its rows draw past, with the total soft aces
+/// bust past if they count soft.
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub deck: Deck,
+    pub threshold: u8,
+    pub soft_aces: Option<u16>,
+}
+
+impl Variant {
+    /// Looks up one of the `VARIANTS` by name.
+    pub fn named(name: &str) -> Option<Self> {
+        let (deck, threshold, soft_aces) = match name {
+            "classic-6deck" => ("six-deck", DEFAULT_THRESHOLD, None),
+            "spanish-40" => ("spanish-40", DEFAULT_THRESHOLD, None),
+            "simplified-40" => ("standard-40", DEFAULT_THRESHOLD, None),
+            "blackjack-21" => ("standard-52", 16, Some(21)),
+            _ => return None,
+        };
+        Some(Variant {
+            deck: Deck::preset(deck).expect("variants deal from presets"),
+            threshold,
+            soft_aces,
+        })
+    }
+}
+
+/// Parses a `--variant` argument, one of the `VARIANTS` by name.
+pub fn parse_arg(arg: &str) -> Result<Variant, String> {
+    Variant::named(arg).ok_or_else(|| {
+        let names: Vec<&str> = VARIANTS.iter().map(|&(name, _)| name).collect();
+        format!("unknown variant '{}' (expected one of: {})", arg, names.join(", "))
+    })
+}